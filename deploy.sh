@@ -104,6 +104,8 @@ create_env_file() {
     read -p "SMTP Username (email): " SMTP_USER
     read -sp "SMTP Password (app password): " SMTP_PASS
     echo
+    # Signs the links in emails; required, at least 32 bytes
+    APP_SECRET=$(openssl rand -hex 32)
     
     cat > .env << EOF
 DATABASE_URL=$DB_URL
@@ -112,6 +114,7 @@ SMTP_HOST=$SMTP_HOST
 SMTP_PORT=$SMTP_PORT
 SMTP_USERNAME=$SMTP_USER
 SMTP_PASSWORD=$SMTP_PASS
+APP_SECRET=$APP_SECRET
 RUST_LOG=weather_alert_system=info,actix_web=info
 EOF
     
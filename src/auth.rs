@@ -0,0 +1,149 @@
+use crate::db::Database;
+use crate::error::AppError;
+use crate::models::ApiToken;
+use crate::tokens::constant_time_eq;
+use crate::AppState;
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// Generates a new random API token. Two v4 UUIDs give 244 random bits from the OS RNG.
+pub fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Tokens are stored as SHA-256 hex digests, so a database leak doesn't leak sessions
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The user behind the `Authorization: Bearer <token>` header of a request. Using this as
+/// a handler argument rejects requests without a valid token with `401`.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub token_id: Uuid,
+}
+
+impl AuthenticatedUser {
+    /// Fails with `403` unless the resource belongs to the authenticated user
+    pub fn ensure_owns(&self, user_id: Uuid) -> Result<(), AppError> {
+        if self.user_id != user_id {
+            return Err(AppError::Forbidden(
+                "You can only access your own account".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let state = state.ok_or_else(|| AppError::Internal("App state missing".to_string()))?;
+            let token = token.ok_or_else(|| {
+                AppError::Unauthorized("Missing bearer token".to_string())
+            })?;
+            let api_token = authenticate(&state.db, &token).await?;
+
+            Ok(AuthenticatedUser {
+                user_id: api_token.user_id,
+                token_id: api_token.id,
+            })
+        })
+    }
+}
+
+/// A caller allowed to use admin-only endpoints: either a user with the `admin` role
+/// (bearer token) or a holder of one of the `ADMIN_API_KEYS` (`X-Admin-Key` header).
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    /// Who is acting, as written to the audit log
+    pub actor: String,
+}
+
+impl AdminUser {
+    /// Writes an entry to the admin audit log
+    pub async fn audit(&self, db: &Database, action: &str, target: Option<&str>) -> Result<(), AppError> {
+        db.record_admin_action(&self.actor, action, target).await
+    }
+}
+
+impl FromRequest for AdminUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+        let admin_key = req
+            .headers()
+            .get("X-Admin-Key")
+            .map(|value| value.as_bytes().to_vec());
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let state = state.ok_or_else(|| AppError::Internal("App state missing".to_string()))?;
+
+            if let Some(key) = admin_key {
+                let matches = state
+                    .config
+                    .admin_api_keys
+                    .iter()
+                    .any(|configured| constant_time_eq(configured.as_bytes(), &key));
+                if !matches {
+                    return Err(AppError::Unauthorized("Invalid admin key".to_string()));
+                }
+                // Identify the key without writing it to the audit log
+                let fingerprint = hash_token(&String::from_utf8_lossy(&key));
+                return Ok(AdminUser {
+                    actor: format!("api_key:{}", &fingerprint[..12]),
+                });
+            }
+
+            let token = token.ok_or_else(|| {
+                AppError::Unauthorized("Admin credentials required".to_string())
+            })?;
+            let api_token = authenticate(&state.db, &token).await?;
+            let user = state
+                .db
+                .get_user_by_id(api_token.user_id)
+                .await?
+                .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+            if user.role != "admin" {
+                return Err(AppError::Forbidden("Admin role required".to_string()));
+            }
+
+            Ok(AdminUser {
+                actor: format!("user:{}", user.id),
+            })
+        })
+    }
+}
+
+/// The token of an `Authorization: Bearer <token>` header
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+async fn authenticate(db: &Database, token: &str) -> Result<ApiToken, AppError> {
+    db.use_api_token(&hash_token(token))
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))
+}
@@ -0,0 +1,184 @@
+use crate::db::Database;
+use crate::models::WeatherData;
+use chrono::{Duration, Utc};
+use log::warn;
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, StrokeDash, Transform};
+
+const WIDTH: u32 = 560;
+const HEIGHT: u32 = 180;
+const PADDING: f32 = 14.0;
+// Head room above and below the plotted values, in °C
+const MARGIN_CELSIUS: f64 = 2.0;
+// Alert charts cover this many hours of readings (at most CHART_MAX_READINGS of them)
+const CHART_WINDOW_HOURS: i64 = 24;
+const CHART_MAX_READINGS: i64 = 48;
+
+/// A small PNG line chart of recent temperatures, embedded in alert emails
+pub struct TemperatureChart {
+    pub png: Vec<u8>,
+    /// Lowest and highest temperature in the plotted readings
+    pub low: f64,
+    pub high: f64,
+}
+
+/// Plots `history` (in any order) as a line, with the user's `min_temp` / `max_temp`
+/// thresholds as dashed lines. Returns `None` when there are fewer than two readings.
+pub fn temperature_chart(
+    history: &[WeatherData],
+    min_temp: Option<i32>,
+    max_temp: Option<i32>,
+) -> Option<TemperatureChart> {
+    let mut readings: Vec<_> = history
+        .iter()
+        .map(|w| (w.fetched_at.timestamp() as f64, w.temperature))
+        .collect();
+    if readings.len() < 2 {
+        return None;
+    }
+    readings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let temps = readings.iter().map(|(_, t)| *t);
+    let low = temps.clone().fold(f64::INFINITY, f64::min);
+    let high = temps.fold(f64::NEG_INFINITY, f64::max);
+
+    // Keep the thresholds in view so the reader sees how far off the limit the weather is
+    let thresholds = [min_temp, max_temp].into_iter().flatten().map(f64::from);
+    let y_min = thresholds.clone().fold(low, f64::min) - MARGIN_CELSIUS;
+    let y_max = thresholds.fold(high, f64::max) + MARGIN_CELSIUS;
+
+    let (t_start, t_end) = (readings[0].0, readings[readings.len() - 1].0);
+    let plot_w = WIDTH as f32 - 2.0 * PADDING;
+    let plot_h = HEIGHT as f32 - 2.0 * PADDING;
+    let x = |t: f64| PADDING + ((t - t_start) / (t_end - t_start).max(1.0)) as f32 * plot_w;
+    let y = |temp: f64| PADDING + ((y_max - temp) / (y_max - y_min)) as f32 * plot_h;
+
+    let mut pixmap = Pixmap::new(WIDTH, HEIGHT)?;
+    pixmap.fill(Color::WHITE);
+
+    // Horizontal grid
+    for i in 0..=4 {
+        let gy = PADDING + plot_h * i as f32 / 4.0;
+        stroke_line(
+            &mut pixmap,
+            (PADDING, gy),
+            (WIDTH as f32 - PADDING, gy),
+            (233, 236, 239),
+            1.0,
+            false,
+        );
+    }
+
+    if let Some(limit) = max_temp {
+        let ty = y(limit as f64);
+        stroke_line(
+            &mut pixmap,
+            (PADDING, ty),
+            (WIDTH as f32 - PADDING, ty),
+            (220, 53, 69),
+            1.5,
+            true,
+        );
+    }
+    if let Some(limit) = min_temp {
+        let ty = y(limit as f64);
+        stroke_line(
+            &mut pixmap,
+            (PADDING, ty),
+            (WIDTH as f32 - PADDING, ty),
+            (13, 110, 253),
+            1.5,
+            true,
+        );
+    }
+
+    let mut line = PathBuilder::new();
+    for (i, (t, temp)) in readings.iter().enumerate() {
+        if i == 0 {
+            line.move_to(x(*t), y(*temp));
+        } else {
+            line.line_to(x(*t), y(*temp));
+        }
+    }
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(102, 126, 234, 255);
+    paint.anti_alias = true;
+    let stroke = Stroke {
+        width: 2.5,
+        ..Stroke::default()
+    };
+    pixmap.stroke_path(
+        &line.finish()?,
+        &paint,
+        &stroke,
+        Transform::identity(),
+        None,
+    );
+
+    for (t, temp) in &readings {
+        if let Some(dot) = PathBuilder::from_circle(x(*t), y(*temp), 3.0) {
+            pixmap.fill_path(&dot, &paint, FillRule::Winding, Transform::identity(), None);
+        }
+    }
+
+    match pixmap.encode_png() {
+        Ok(png) => Some(TemperatureChart { png, low, high }),
+        Err(e) => {
+            warn!("⚠️ Could not encode temperature chart: {}", e);
+            None
+        }
+    }
+}
+
+/// Charts the last 24 hours of temperatures for `city` with the user's thresholds.
+/// A chart is a nice-to-have, so lookup failures only skip it.
+pub async fn alert_chart(db: &Database, city: &str, user_id: Option<uuid::Uuid>) -> Option<TemperatureChart> {
+    let since = Utc::now() - Duration::hours(CHART_WINDOW_HOURS);
+    let recent = match db
+        .get_weather_history(city, Some(since), None, None, CHART_MAX_READINGS)
+        .await
+    {
+        Ok(history) => history.items,
+        Err(e) => {
+            warn!("⚠️ Could not load weather history for chart of {}: {}", city, e);
+            return None;
+        }
+    };
+
+    let prefs = match user_id {
+        Some(user_id) => db.get_user_preferences(user_id).await.ok().flatten(),
+        None => None,
+    };
+    let (min_temp, max_temp) = prefs.map_or((None, None), |p| (p.min_temp, p.max_temp));
+
+    temperature_chart(&recent, min_temp, max_temp)
+}
+
+fn stroke_line(
+    pixmap: &mut Pixmap,
+    from: (f32, f32),
+    to: (f32, f32),
+    (r, g, b): (u8, u8, u8),
+    width: f32,
+    dashed: bool,
+) {
+    let mut path = PathBuilder::new();
+    path.move_to(from.0, from.1);
+    path.line_to(to.0, to.1);
+    let Some(path) = path.finish() else {
+        return;
+    };
+
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, 255);
+    paint.anti_alias = true;
+    let stroke = Stroke {
+        width,
+        dash: if dashed {
+            StrokeDash::new(vec![6.0, 4.0], 0.0)
+        } else {
+            None
+        },
+        ..Stroke::default()
+    };
+    pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+}
@@ -2,33 +2,273 @@
 use crate::error::AppError;
 use std::env;
 
+// HMAC-SHA256 key for signed links; shorter secrets are easier to guess
+const MIN_APP_SECRET_BYTES: usize = 32;
+
+/// How outgoing email is delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailTransport {
+    /// Authenticated SMTP relay (TLS, credentials required)
+    Smtp,
+    /// Plain, unauthenticated SMTP, e.g. MailHog or Mailpit on localhost
+    SmtpLocal,
+    /// Writes each message as an `.eml` file into `MAIL_FILE_DIR`
+    File,
+    /// Prints each message to stdout
+    Stdout,
+}
+
+impl std::str::FromStr for MailTransport {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "smtp" => Ok(MailTransport::Smtp),
+            "smtp-local" => Ok(MailTransport::SmtpLocal),
+            "file" => Ok(MailTransport::File),
+            "stdout" => Ok(MailTransport::Stdout),
+            other => Err(AppError::Config(format!(
+                "Unknown MAIL_TRANSPORT '{}' (expected smtp, smtp-local, file or stdout)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Transport security for `MAIL_TRANSPORT=smtp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plaintext, e.g. an internal relay on port 25
+    None,
+    /// Plaintext connection upgraded with STARTTLS, which is required (usually port 587)
+    Starttls,
+    /// TLS from the first byte (usually port 465)
+    Implicit,
+}
+
+impl std::str::FromStr for SmtpTls {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::Starttls),
+            "implicit" => Ok(SmtpTls::Implicit),
+            other => Err(AppError::Config(format!(
+                "Unknown SMTP_TLS '{}' (expected none, starttls or implicit)",
+                other
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub weather_api_key: String,
+    pub mail_transport: MailTransport,
+    pub mail_from: String,
+    pub mail_from_name: Option<String>,
+    pub mail_reply_to: Option<String>,
+    pub message_id_domain: Option<String>,
+    pub mail_file_dir: String,
     pub smtp_host: String,
     pub smtp_port: u16,
-    pub smtp_username: String,
-    pub smtp_password: String,
+    pub smtp_tls: SmtpTls,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_pool_size: u32,
+    pub email_concurrency: usize,
+    pub outbox_poll_interval_secs: u64,
+    pub outbox_batch_size: i64,
+    pub outbox_max_attempts: i32,
+    pub outbox_retry_base_secs: i64,
+    pub templates_dir: String,
+    pub app_secret: String,
+    pub public_base_url: String,
+    pub subscription_token_ttl_days: i64,
+    pub pending_user_ttl_hours: i64,
+    pub suppress_after_failures: i32,
+    pub alert_max_per_user_per_day: i64,
+    pub login_link_ttl_minutes: i64,
+    pub stream_token_ttl_minutes: i64,
+    pub api_token_ttl_days: i32,
+    pub admin_api_keys: Vec<String>,
+    pub email_max_per_minute: i64,
+    pub mail_webhook_secret: Option<String>,
+    pub rate_limit_api_per_minute: u32,
+    pub rate_limit_signup_per_hour: u32,
+    pub rate_limit_fetch_per_hour: u32,
+    pub rate_limit_trust_proxy: bool,
+    pub idempotency_key_ttl_hours: i64,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, AppError> {
+        let mail_transport: MailTransport = env::var("MAIL_TRANSPORT")
+            .unwrap_or_else(|_| "smtp".to_string())
+            .parse()?;
+        let smtp_username = env::var("SMTP_USERNAME").ok();
+        let smtp_password = env::var("SMTP_PASSWORD").ok();
+
+        let mail_from = env::var("MAIL_FROM")
+            .ok()
+            .or_else(|| smtp_username.clone())
+            .unwrap_or_else(|| "weather-alerts@localhost".to_string());
+
+        let app_secret = env::var("APP_SECRET")
+            .map_err(|_| AppError::Config("APP_SECRET not set".to_string()))?;
+        if app_secret.len() < MIN_APP_SECRET_BYTES {
+            return Err(AppError::Config(format!(
+                "APP_SECRET must be at least {} bytes long, e.g. from `openssl rand -hex 32`",
+                MIN_APP_SECRET_BYTES
+            )));
+        }
+
+        let default_smtp_port = match mail_transport {
+            MailTransport::SmtpLocal => "1025",
+            _ => "587",
+        };
+        let smtp_port: u16 = env::var("SMTP_PORT")
+            .unwrap_or_else(|_| default_smtp_port.to_string())
+            .parse()
+            .map_err(|_| AppError::Config("SMTP_PORT must be a port number (1-65535)".to_string()))?;
+
+        // Port 465 is the conventional implicit-TLS port; everything else gets STARTTLS
+        let smtp_tls = match env::var("SMTP_TLS") {
+            Ok(mode) => mode.parse()?,
+            Err(_) if smtp_port == 465 => SmtpTls::Implicit,
+            Err(_) => SmtpTls::Starttls,
+        };
+
+        // Only the authenticated relay needs credentials; the development transports run
+        // without any external mail service. Without TLS there is nothing to protect the
+        // password, so plaintext relays must be unauthenticated.
+        if mail_transport == MailTransport::Smtp {
+            let has_credentials = smtp_username.is_some() || smtp_password.is_some();
+            if smtp_tls == SmtpTls::None && has_credentials {
+                return Err(AppError::Config(
+                    "SMTP_USERNAME and SMTP_PASSWORD require SMTP_TLS=starttls or implicit".to_string(),
+                ));
+            }
+            if smtp_tls != SmtpTls::None {
+                if smtp_username.is_none() {
+                    return Err(AppError::Config("SMTP_USERNAME not set".to_string()));
+                }
+                if smtp_password.is_none() {
+                    return Err(AppError::Config("SMTP_PASSWORD not set".to_string()));
+                }
+            }
+        }
+
         Ok(Self {
             database_url: env::var("DATABASE_URL")
                 .map_err(|_| AppError::Config("DATABASE_URL not set".to_string()))?,
             weather_api_key: env::var("WEATHER_API_KEY")
                 .map_err(|_| AppError::Config("WEATHER_API_KEY not set".to_string()))?,
-            smtp_host: env::var("SMTP_HOST")
-                .unwrap_or_else(|_| "smtp.gmail.com".to_string()),
-            smtp_port: env::var("SMTP_PORT")
-                .unwrap_or_else(|_| "587".to_string())
-                .parse()
-                .unwrap_or(587),
-            smtp_username: env::var("SMTP_USERNAME")
-                .map_err(|_| AppError::Config("SMTP_USERNAME not set".to_string()))?,
-            smtp_password: env::var("SMTP_PASSWORD")
-                .map_err(|_| AppError::Config("SMTP_PASSWORD not set".to_string()))?,
+            mail_transport,
+            mail_from,
+            mail_from_name: env::var("MAIL_FROM_NAME").ok(),
+            mail_reply_to: env::var("MAIL_REPLY_TO").ok(),
+            message_id_domain: env::var("MESSAGE_ID_DOMAIN").ok(),
+            mail_file_dir: env::var("MAIL_FILE_DIR").unwrap_or_else(|_| "./mail".to_string()),
+            smtp_host: env::var("SMTP_HOST").unwrap_or_else(|_| match mail_transport {
+                MailTransport::SmtpLocal => "localhost".to_string(),
+                _ => "smtp.gmail.com".to_string(),
+            }),
+            smtp_port,
+            smtp_tls,
+            smtp_username,
+            smtp_password,
+            smtp_pool_size: env::var("SMTP_POOL_SIZE")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .unwrap_or(4),
+            email_concurrency: env::var("EMAIL_CONCURRENCY")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse()
+                .unwrap_or(8),
+            outbox_poll_interval_secs: env::var("OUTBOX_POLL_INTERVAL_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            outbox_batch_size: env::var("OUTBOX_BATCH_SIZE")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .unwrap_or(20),
+            outbox_max_attempts: env::var("OUTBOX_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            outbox_retry_base_secs: env::var("OUTBOX_RETRY_BASE_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            templates_dir: env::var("TEMPLATES_DIR").unwrap_or_else(|_| "./templates".to_string()),
+            app_secret,
+            public_base_url: env::var("PUBLIC_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:8080".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            subscription_token_ttl_days: env::var("SUBSCRIPTION_TOKEN_TTL_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            pending_user_ttl_hours: env::var("PENDING_USER_TTL_HOURS")
+                .unwrap_or_else(|_| "48".to_string())
+                .parse()
+                .unwrap_or(48),
+            suppress_after_failures: env::var("SUPPRESS_AFTER_PERMANENT_FAILURES")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
+            mail_webhook_secret: env::var("MAIL_WEBHOOK_SECRET").ok(),
+            alert_max_per_user_per_day: env::var("ALERT_MAX_PER_USER_PER_DAY")
+                .unwrap_or_else(|_| "6".to_string())
+                .parse()
+                .unwrap_or(6),
+            email_max_per_minute: env::var("EMAIL_MAX_PER_MINUTE")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            login_link_ttl_minutes: env::var("LOGIN_LINK_TTL_MINUTES")
+                .unwrap_or_else(|_| "15".to_string())
+                .parse()
+                .unwrap_or(15),
+            stream_token_ttl_minutes: env::var("STREAM_TOKEN_TTL_MINUTES")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            api_token_ttl_days: env::var("API_TOKEN_TTL_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            admin_api_keys: env::var("ADMIN_API_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect(),
+            rate_limit_api_per_minute: env::var("RATE_LIMIT_API_PER_MINUTE")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .unwrap_or(120),
+            rate_limit_signup_per_hour: env::var("RATE_LIMIT_SIGNUP_PER_HOUR")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            rate_limit_fetch_per_hour: env::var("RATE_LIMIT_FETCH_PER_HOUR")
+                .unwrap_or_else(|_| "6".to_string())
+                .parse()
+                .unwrap_or(6),
+            rate_limit_trust_proxy: env::var("RATE_LIMIT_TRUST_PROXY")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            idempotency_key_ttl_hours: env::var("IDEMPOTENCY_KEY_TTL_HOURS")
+                .unwrap_or_else(|_| "24".to_string())
+                .parse()
+                .unwrap_or(24),
         })
     }
 }
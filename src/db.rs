@@ -1,5 +1,8 @@
 use crate::error::AppError;
 use crate::models::*;
+use crate::pagination::{Cursor, Page};
+use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
 use log::info;
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use uuid::Uuid;
@@ -39,6 +42,13 @@ impl Database {
             "#,
             "CREATE INDEX IF NOT EXISTS idx_users_city ON users(city);",
             "CREATE INDEX IF NOT EXISTS idx_users_email ON users(email);",
+            "ALTER TABLE users ADD COLUMN IF NOT EXISTS locale VARCHAR(10) NOT NULL DEFAULT 'en';",
+            "ALTER TABLE users ADD COLUMN IF NOT EXISTS status VARCHAR(20) NOT NULL DEFAULT 'active';",
+            "ALTER TABLE users ADD COLUMN IF NOT EXISTS role VARCHAR(20) NOT NULL DEFAULT 'user';",
+            // Set once an address was confirmed, so users re-confirming a changed address
+            // aren't cleaned up as abandoned registrations
+            "ALTER TABLE users ADD COLUMN IF NOT EXISTS verified_at TIMESTAMP WITH TIME ZONE;",
+            "UPDATE users SET verified_at = created_at WHERE verified_at IS NULL AND status <> 'pending';",
             r#"
             CREATE TABLE IF NOT EXISTS user_preferences (
                 id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
@@ -82,6 +92,133 @@ impl Database {
             "#,
             "CREATE INDEX IF NOT EXISTS idx_alerts_user_id ON alert_logs(user_id);",
             "CREATE INDEX IF NOT EXISTS idx_alerts_sent_at ON alert_logs(sent_at DESC);",
+            // Delivery tracking; rows logged before these columns existed were only written
+            // after a successful send
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS channel VARCHAR(20) NOT NULL DEFAULT 'email';",
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS status VARCHAR(20) NOT NULL DEFAULT 'sent';",
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 1;",
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS last_error TEXT;",
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS provider_message_id VARCHAR(255);",
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();",
+            "CREATE INDEX IF NOT EXISTS idx_alerts_provider_message_id ON alert_logs(provider_message_id);",
+            // Why an alert was not sent on its own, e.g. deferred by the daily cap
+            "ALTER TABLE alert_logs ADD COLUMN IF NOT EXISTS status_reason TEXT;",
+            "CREATE INDEX IF NOT EXISTS idx_alerts_status ON alert_logs(status);",
+            // Alerts used to be logged as `temperature` whatever their kind; their English
+            // message tells the kind apart so kind and severity filters match them
+            r#"
+            UPDATE alert_logs SET alert_type = CASE
+                WHEN message LIKE '%Low temperature alert%' THEN 'low_temperature'
+                WHEN message LIKE '%Rain alert%' THEN 'rain'
+                WHEN message LIKE '%Snow alert%' THEN 'snow'
+                WHEN message LIKE '%Storm alert%' THEN 'storm'
+                ELSE 'high_temperature'
+            END
+            WHERE alert_type = 'temperature';
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS outbox (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+                kind VARCHAR(50) NOT NULL,
+                recipient VARCHAR(255) NOT NULL,
+                payload JSONB NOT NULL,
+                status VARCHAR(20) NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                max_attempts INTEGER NOT NULL DEFAULT 5,
+                next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                locked_until TIMESTAMP WITH TIME ZONE,
+                last_error TEXT,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            );
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_outbox_due ON outbox(next_attempt_at) WHERE status IN ('pending', 'processing');",
+            "CREATE INDEX IF NOT EXISTS idx_outbox_status ON outbox(status, updated_at DESC);",
+            "ALTER TABLE outbox ADD COLUMN IF NOT EXISTS alert_log_id UUID REFERENCES alert_logs(id) ON DELETE SET NULL;",
+            r#"
+            CREATE TABLE IF NOT EXISTS suppressions (
+                email VARCHAR(255) PRIMARY KEY,
+                reason VARCHAR(50) NOT NULL,
+                details TEXT,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            );
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS email_failures (
+                email VARCHAR(255) PRIMARY KEY,
+                permanent_failures INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            );
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS api_tokens (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                token_hash VARCHAR(64) NOT NULL UNIQUE,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                last_used_at TIMESTAMP WITH TIME ZONE
+            );
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_api_tokens_user_id ON api_tokens(user_id);",
+            // Sign-in links that were exchanged, kept until they would have expired anyway
+            r#"
+            CREATE TABLE IF NOT EXISTS used_login_links (
+                token_hash VARCHAR(64) PRIMARY KEY,
+                used_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS admin_audit_log (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                actor VARCHAR(100) NOT NULL,
+                action VARCHAR(100) NOT NULL,
+                target TEXT,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            );
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_admin_audit_created_at ON admin_audit_log(created_at DESC);",
+            r#"
+            CREATE TABLE IF NOT EXISTS job_runs (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                kind VARCHAR(50) NOT NULL,
+                source VARCHAR(20) NOT NULL,
+                actor VARCHAR(100),
+                status VARCHAR(20) NOT NULL DEFAULT 'running',
+                cities_total INTEGER NOT NULL DEFAULT 0,
+                cities_succeeded INTEGER NOT NULL DEFAULT 0,
+                cities_failed INTEGER NOT NULL DEFAULT 0,
+                alerts_queued INTEGER NOT NULL DEFAULT 0,
+                alerts_deferred INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                finished_at TIMESTAMP WITH TIME ZONE,
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            );
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_job_runs_started_at ON job_runs(started_at DESC);",
+            r#"
+            CREATE TABLE IF NOT EXISTS idempotency_keys (
+                scope TEXT NOT NULL,
+                key VARCHAR(255) NOT NULL,
+                request_hash VARCHAR(64) NOT NULL,
+                status VARCHAR(20) NOT NULL DEFAULT 'in_progress',
+                status_code INTEGER,
+                response_headers JSONB,
+                response_body BYTEA,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (scope, key)
+            );
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_idempotency_keys_expires_at ON idempotency_keys(expires_at);",
+            // Keyset pagination walks these (timestamp, id) orders
+            "CREATE INDEX IF NOT EXISTS idx_users_created_at_id ON users(created_at DESC, id DESC);",
+            "CREATE INDEX IF NOT EXISTS idx_alerts_sent_at_id ON alert_logs(sent_at DESC, id DESC);",
+            "CREATE INDEX IF NOT EXISTS idx_weather_city_fetched_at ON weather_data(LOWER(city), fetched_at DESC, id DESC);",
         ];
 
         // The loop now executes each command individually
@@ -99,14 +236,15 @@ impl Database {
     pub async fn create_user(&self, req: &CreateUserRequest) -> Result<User, AppError> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            INSERT INTO users (email, city, country)
-            VALUES ($1, $2, UPPER($3))
+            INSERT INTO users (email, city, country, locale, status)
+            VALUES ($1, $2, UPPER($3), COALESCE($4, 'en'), 'pending')
             RETURNING *
             "#,
         )
         .bind(&req.email)
         .bind(&req.city)
         .bind(&req.country)
+        .bind(&req.locale)
         .fetch_one(&self.pool)
         .await?;
 
@@ -141,7 +279,7 @@ impl Database {
     pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            SELECT * FROM users WHERE email = $1
+            SELECT * FROM users WHERE LOWER(email) = LOWER($1)
             "#,
         )
         .bind(email)
@@ -151,22 +289,41 @@ impl Database {
         Ok(user)
     }
 
-    pub async fn get_all_users(&self) -> Result<Vec<User>, AppError> {
+    /// One page of users, newest first
+    pub async fn get_all_users(
+        &self,
+        filter: &UserFilter,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Page<User>, AppError> {
         let users = sqlx::query_as::<_, User>(
             r#"
-            SELECT * FROM users ORDER BY created_at DESC
+            SELECT * FROM users
+            WHERE ($1::text IS NULL OR LOWER(city) = LOWER($1))
+              AND ($2::text IS NULL OR UPPER(country) = UPPER($2))
+              AND ($3::timestamptz IS NULL OR (created_at, id) < ($3, $4))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $5
             "#,
         )
+        .bind(&filter.city)
+        .bind(&filter.country)
+        .bind(after.map(|c| c.at))
+        .bind(after.map(|c| c.id))
+        .bind(limit + 1)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(users)
+        Ok(Page::from_rows(users, limit, |u| Cursor {
+            at: u.created_at,
+            id: u.id,
+        }))
     }
 
     pub async fn get_users_by_city(&self, city: &str) -> Result<Vec<User>, AppError> {
         let users = sqlx::query_as::<_, User>(
             r#"
-            SELECT * FROM users WHERE LOWER(city) = LOWER($1)
+            SELECT * FROM users WHERE LOWER(city) = LOWER($1) AND status = 'active'
             "#,
         )
         .bind(city)
@@ -180,6 +337,7 @@ impl Database {
         let cities = sqlx::query_as::<_, CityInfo>(
             r#"
             SELECT DISTINCT city, country FROM users
+            WHERE status = 'active'
             ORDER BY city
             "#,
         )
@@ -189,154 +347,351 @@ impl Database {
         Ok(cities)
     }
 
-    // Preferences operations
-    pub async fn get_user_preferences(&self, user_id: Uuid) -> Result<Option<UserPreferences>, AppError> {
-        let prefs = sqlx::query_as::<_, UserPreferences>(
+    /// Moves a user from status `from` to `status`. Returns `None` if the user does not
+    /// exist or no longer has status `from`.
+    pub async fn set_user_status(
+        &self,
+        user_id: Uuid,
+        from: &str,
+        status: &str,
+    ) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as::<_, User>(
             r#"
-            SELECT * FROM user_preferences WHERE user_id = $1
+            UPDATE users SET status = $3 WHERE id = $1 AND status = $2
+            RETURNING *
             "#,
         )
         .bind(user_id)
+        .bind(from)
+        .bind(status)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(prefs)
+        if let Some(user) = &user {
+            info!("✅ User {} is now {}", user.email, user.status);
+        }
+        Ok(user)
     }
 
-    pub async fn update_user_preferences(
+    pub async fn set_user_role(&self, email: &str, role: &str) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users SET role = $2 WHERE email = $1
+            RETURNING *
+            "#,
+        )
+        .bind(email)
+        .bind(role)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(user) = &user {
+            info!("✅ User {} now has role {}", user.email, user.role);
+        }
+        Ok(user)
+    }
+
+    /// Marks a pending user as verified if `email` is still their address. Returns `None`
+    /// if the user does not exist, was not pending or has changed their address since.
+    pub async fn activate_pending_user(
         &self,
         user_id: Uuid,
-        req: &UpdatePreferencesRequest,
-    ) -> Result<UserPreferences, AppError> {
-        let prefs = sqlx::query_as::<_, UserPreferences>(
+        email: &str,
+    ) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as::<_, User>(
             r#"
-            UPDATE user_preferences
-            SET
-                min_temp = COALESCE($2, min_temp),
-                max_temp = COALESCE($3, max_temp),
-                alert_on_rain = COALESCE($4, alert_on_rain),
-                alert_on_snow = COALESCE($5, alert_on_snow),
-                alert_on_storm = COALESCE($6, alert_on_storm),
-                updated_at = NOW()
-            WHERE user_id = $1
+            UPDATE users SET status = 'active', verified_at = NOW()
+            WHERE id = $1 AND status = 'pending' AND email = $2
             RETURNING *
             "#,
         )
         .bind(user_id)
-        .bind(req.min_temp)
-        .bind(req.max_temp)
-        .bind(req.alert_on_rain)
-        .bind(req.alert_on_snow)
-        .bind(req.alert_on_storm)
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Removes registrations that were never confirmed within `max_age_hours`
+    pub async fn delete_expired_pending_users(&self, max_age_hours: i64) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE status = 'pending'
+              AND verified_at IS NULL
+              AND created_at < NOW() - make_interval(hours => $1::INTEGER)
+            "#,
+        )
+        .bind(max_age_hours)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // API tokens
+    pub async fn create_api_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        ttl_days: i32,
+    ) -> Result<ApiToken, AppError> {
+        let token = sqlx::query_as::<_, ApiToken>(
+            r#"
+            INSERT INTO api_tokens (user_id, token_hash, expires_at)
+            VALUES ($1, $2, NOW() + make_interval(days => $3))
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(ttl_days)
         .fetch_one(&self.pool)
         .await?;
 
-        info!("✅ Preferences updated for user: {}", user_id);
-        Ok(prefs)
+        Ok(token)
     }
 
-    // Weather data operations
-    pub async fn store_weather_data(&self, weather: &WeatherData) -> Result<(), AppError> {
+    /// Looks up an unexpired token by hash and records that it was used
+    pub async fn use_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>, AppError> {
+        let token = sqlx::query_as::<_, ApiToken>(
+            r#"
+            UPDATE api_tokens
+            SET last_used_at = NOW()
+            WHERE token_hash = $1 AND expires_at > NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Looks up an unexpired token by hash without recording a use
+    pub async fn get_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>, AppError> {
+        let token = sqlx::query_as::<_, ApiToken>(
+            r#"
+            SELECT * FROM api_tokens
+            WHERE token_hash = $1 AND expires_at > NOW()
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Unexpired tokens of a user, newest first
+    pub async fn get_user_api_tokens(&self, user_id: Uuid) -> Result<Vec<ApiToken>, AppError> {
+        let tokens = sqlx::query_as::<_, ApiToken>(
+            r#"
+            SELECT * FROM api_tokens
+            WHERE user_id = $1 AND expires_at > NOW()
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tokens)
+    }
+
+    pub async fn delete_api_token(&self, id: Uuid) -> Result<(), AppError> {
         sqlx::query(
             r#"
-            INSERT INTO weather_data
-            (city, country, temperature, feels_like, conditions, description,
-             humidity, wind_speed, pressure, fetched_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            DELETE FROM api_tokens WHERE id = $1
             "#,
         )
-        .bind(&weather.city)
-        .bind(&weather.country)
-        .bind(weather.temperature)
-        .bind(weather.feels_like)
-        .bind(&weather.conditions)
-        .bind(&weather.description)
-        .bind(weather.humidity)
-        .bind(weather.wind_speed)
-        .bind(weather.pressure)
-        .bind(weather.fetched_at)
+        .bind(id)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn get_latest_weather(&self, city: &str) -> Result<Option<WeatherData>, AppError> {
-        let weather = sqlx::query_as::<_, WeatherData>(
+    pub async fn delete_expired_api_tokens(&self) -> Result<u64, AppError> {
+        let result = sqlx::query(
             r#"
-            SELECT * FROM weather_data
-            WHERE LOWER(city) = LOWER($1)
-            ORDER BY fetched_at DESC
-            LIMIT 1
+            DELETE FROM api_tokens WHERE expires_at <= NOW()
             "#,
         )
-        .bind(city)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Records that a sign-in link was exchanged. Returns `false` if it already was.
+    pub async fn use_login_link(&self, token_hash: &str, ttl_minutes: i64) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO used_login_links (token_hash, expires_at)
+            VALUES ($1, NOW() + make_interval(mins => $2::INTEGER))
+            ON CONFLICT (token_hash) DO NOTHING
+            "#,
+        )
+        .bind(token_hash)
+        .bind(ttl_minutes)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn delete_expired_login_links(&self) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM used_login_links WHERE expires_at <= NOW()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Idempotency keys
+    /// Claims `key` for a new request. Returns `false` if the key is already taken by a
+    /// live request; expired keys and requests abandoned mid-way are claimed anew.
+    pub async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        request_hash: &str,
+        ttl_hours: i64,
+    ) -> Result<bool, AppError> {
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (scope, key, request_hash, expires_at)
+            VALUES ($1, $2, $3, NOW() + make_interval(hours => $4::INTEGER))
+            ON CONFLICT (scope, key) DO UPDATE
+            SET request_hash = EXCLUDED.request_hash,
+                status = 'in_progress',
+                status_code = NULL,
+                response_headers = NULL,
+                response_body = NULL,
+                created_at = NOW(),
+                expires_at = EXCLUDED.expires_at
+            WHERE idempotency_keys.expires_at <= NOW()
+               OR (idempotency_keys.status = 'in_progress'
+                   AND idempotency_keys.created_at < NOW() - INTERVAL '5 minutes')
+            RETURNING key
+            "#,
+        )
+        .bind(scope)
+        .bind(key)
+        .bind(request_hash)
+        .bind(ttl_hours)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(weather)
+        Ok(claimed.is_some())
     }
 
-    pub async fn get_weather_history(
+    pub async fn get_idempotency_key(
         &self,
-        city: &str,
-        limit: i64,
-    ) -> Result<Vec<WeatherData>, AppError> {
-        let history = sqlx::query_as::<_, WeatherData>(
+        scope: &str,
+        key: &str,
+    ) -> Result<Option<IdempotencyKey>, AppError> {
+        let record = sqlx::query_as::<_, IdempotencyKey>(
             r#"
-            SELECT * FROM weather_data
-            WHERE LOWER(city) = LOWER($1)
-            ORDER BY fetched_at DESC
-            LIMIT $2
+            SELECT * FROM idempotency_keys WHERE scope = $1 AND key = $2
             "#,
         )
-        .bind(city)
-        .bind(limit)
-        .fetch_all(&self.pool)
+        .bind(scope)
+        .bind(key)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(history)
+        Ok(record)
     }
 
-    // Alert logs
-    pub async fn log_alert(&self, user_id: Uuid, alert_type: &str, message: &str) -> Result<(), AppError> {
+    /// Stores the response to replay for repeats of the request
+    pub async fn complete_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        status_code: i32,
+        headers: &serde_json::Value,
+        body: &[u8],
+    ) -> Result<(), AppError> {
         sqlx::query(
             r#"
-            INSERT INTO alert_logs (user_id, alert_type, message)
-            VALUES ($1, $2, $3)
+            UPDATE idempotency_keys
+            SET status = 'completed', status_code = $3, response_headers = $4, response_body = $5
+            WHERE scope = $1 AND key = $2
             "#,
         )
-        .bind(user_id)
-        .bind(alert_type)
-        .bind(message)
+        .bind(scope)
+        .bind(key)
+        .bind(status_code)
+        .bind(headers)
+        .bind(body)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn get_user_alerts(&self, user_id: Uuid, limit: i64) -> Result<Vec<AlertLog>, AppError> {
-        let alerts = sqlx::query_as::<_, AlertLog>(
+    /// Frees a key whose request failed on the server side, so a retry runs it again
+    pub async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<(), AppError> {
+        sqlx::query(
             r#"
-            SELECT * FROM alert_logs
-            WHERE user_id = $1
-            ORDER BY sent_at DESC
-            LIMIT $2
+            DELETE FROM idempotency_keys WHERE scope = $1 AND key = $2
             "#,
         )
-        .bind(user_id)
-        .bind(limit)
-        .fetch_all(&self.pool)
+        .bind(scope)
+        .bind(key)
+        .execute(&self.pool)
         .await?;
 
-        Ok(alerts)
+        Ok(())
     }
 
-    pub async fn get_all_alerts(&self, limit: i64) -> Result<Vec<AlertLog>, AppError> {
-        let alerts = sqlx::query_as::<_, AlertLog>(
+    pub async fn delete_expired_idempotency_keys(&self) -> Result<u64, AppError> {
+        let result = sqlx::query(
             r#"
-            SELECT * FROM alert_logs
-            ORDER BY sent_at DESC
+            DELETE FROM idempotency_keys WHERE expires_at <= NOW()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Admin audit log
+    pub async fn record_admin_action(
+        &self,
+        actor: &str,
+        action: &str,
+        target: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO admin_audit_log (actor, action, target)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(actor)
+        .bind(action)
+        .bind(target)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_admin_audit_log(&self, limit: i64) -> Result<Vec<AdminAuditEntry>, AppError> {
+        let entries = sqlx::query_as::<_, AdminAuditEntry>(
+            r#"
+            SELECT * FROM admin_audit_log
+            ORDER BY created_at DESC
             LIMIT $1
             "#,
         )
@@ -344,6 +699,1134 @@ impl Database {
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(alerts)
+        Ok(entries)
+    }
+
+    /// Applies a partial update. With `reverify` the user goes back to `pending` until the
+    /// new email address is confirmed.
+    pub async fn update_user(
+        &self,
+        user_id: Uuid,
+        req: &UpdateUserRequest,
+        reverify: bool,
+    ) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET email = COALESCE($2, email),
+                city = COALESCE($3, city),
+                country = UPPER(COALESCE($4, country)),
+                locale = COALESCE($5, locale),
+                status = CASE WHEN $6 THEN 'pending' ELSE status END
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(&req.email)
+        .bind(&req.city)
+        .bind(&req.country)
+        .bind(&req.locale)
+        .bind(reverify)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(user) = &user {
+            info!("✏️ User updated: {}", user.id);
+        }
+        Ok(user)
     }
-}
\ No newline at end of file
+
+    /// Deletes the user; preferences, alert logs, queued notifications and API tokens go
+    /// with it through `ON DELETE CASCADE`
+    pub async fn delete_user(&self, user_id: Uuid) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            info!("🗑️ User deleted: {}", user_id);
+        }
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Preferences operations
+    pub async fn get_user_preferences(&self, user_id: Uuid) -> Result<Option<UserPreferences>, AppError> {
+        let prefs = sqlx::query_as::<_, UserPreferences>(
+            r#"
+            SELECT * FROM user_preferences WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(prefs)
+    }
+
+    /// Preferences of several users in one query, for batched GraphQL loads
+    pub async fn get_preferences_for_users(
+        &self,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<UserPreferences>, AppError> {
+        let prefs = sqlx::query_as::<_, UserPreferences>(
+            r#"
+            SELECT * FROM user_preferences WHERE user_id = ANY($1)
+            "#,
+        )
+        .bind(user_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(prefs)
+    }
+
+    pub async fn update_user_preferences(
+        &self,
+        user_id: Uuid,
+        req: &UpdatePreferencesRequest,
+    ) -> Result<UserPreferences, AppError> {
+        let prefs = sqlx::query_as::<_, UserPreferences>(
+            r#"
+            UPDATE user_preferences
+            SET
+                min_temp = COALESCE($2, min_temp),
+                max_temp = COALESCE($3, max_temp),
+                alert_on_rain = COALESCE($4, alert_on_rain),
+                alert_on_snow = COALESCE($5, alert_on_snow),
+                alert_on_storm = COALESCE($6, alert_on_storm),
+                updated_at = NOW()
+            WHERE user_id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(req.min_temp)
+        .bind(req.max_temp)
+        .bind(req.alert_on_rain)
+        .bind(req.alert_on_snow)
+        .bind(req.alert_on_storm)
+        .fetch_one(&self.pool)
+        .await?;
+
+        info!("✅ Preferences updated for user: {}", user_id);
+        Ok(prefs)
+    }
+
+    // Weather data operations
+    pub async fn store_weather_data(&self, weather: &WeatherData) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO weather_data
+            (city, country, temperature, feels_like, conditions, description,
+             humidity, wind_speed, pressure, fetched_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+        )
+        .bind(&weather.city)
+        .bind(&weather.country)
+        .bind(weather.temperature)
+        .bind(weather.feels_like)
+        .bind(&weather.conditions)
+        .bind(&weather.description)
+        .bind(weather.humidity)
+        .bind(weather.wind_speed)
+        .bind(weather.pressure)
+        .bind(weather.fetched_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_weather_by_id(&self, id: Uuid) -> Result<Option<WeatherData>, AppError> {
+        let weather = sqlx::query_as::<_, WeatherData>(
+            r#"
+            SELECT * FROM weather_data WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(weather)
+    }
+
+    pub async fn get_latest_weather(&self, city: &str) -> Result<Option<WeatherData>, AppError> {
+        let weather = sqlx::query_as::<_, WeatherData>(
+            r#"
+            SELECT * FROM weather_data
+            WHERE LOWER(city) = LOWER($1)
+            ORDER BY fetched_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(city)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(weather)
+    }
+
+    /// Latest reading of each of `cities` (compared case-insensitively) in one query
+    pub async fn get_latest_weather_for_cities(
+        &self,
+        cities: &[String],
+    ) -> Result<Vec<WeatherData>, AppError> {
+        let weather = sqlx::query_as::<_, WeatherData>(
+            r#"
+            SELECT DISTINCT ON (LOWER(city)) * FROM weather_data
+            WHERE LOWER(city) = ANY(SELECT LOWER(c) FROM UNNEST($1::text[]) AS c)
+            ORDER BY LOWER(city), fetched_at DESC
+            "#,
+        )
+        .bind(cities)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(weather)
+    }
+
+    /// One page of readings for `city`, newest first, fetched in `[from, to)`
+    pub async fn get_weather_history(
+        &self,
+        city: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Page<WeatherData>, AppError> {
+        let history = sqlx::query_as::<_, WeatherData>(
+            r#"
+            SELECT * FROM weather_data
+            WHERE LOWER(city) = LOWER($1)
+              AND ($2::timestamptz IS NULL OR fetched_at >= $2)
+              AND ($3::timestamptz IS NULL OR fetched_at < $3)
+              AND ($4::timestamptz IS NULL OR (fetched_at, id) < ($4, $5))
+            ORDER BY fetched_at DESC, id DESC
+            LIMIT $6
+            "#,
+        )
+        .bind(city)
+        .bind(from)
+        .bind(to)
+        .bind(after.map(|c| c.at))
+        .bind(after.map(|c| c.id))
+        .bind(limit + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Page::from_rows(history, limit, |w| Cursor {
+            at: w.fetched_at,
+            id: w.id,
+        }))
+    }
+
+    /// Streams matching readings oldest first, without loading them all into memory
+    pub fn stream_weather_data<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+    ) -> BoxStream<'a, Result<WeatherData, AppError>> {
+        sqlx::query_as::<_, WeatherData>(
+            r#"
+            SELECT * FROM weather_data
+            WHERE ($1::text IS NULL OR LOWER(city) = LOWER($1))
+              AND ($2::text IS NULL OR UPPER(country) = UPPER($2))
+              AND ($3::timestamptz IS NULL OR fetched_at >= $3)
+              AND ($4::timestamptz IS NULL OR fetched_at < $4)
+            ORDER BY fetched_at, id
+            "#,
+        )
+        .bind(&filter.city)
+        .bind(&filter.country)
+        .bind(filter.from)
+        .bind(filter.to)
+        .fetch(&self.pool)
+        .map_err(AppError::from)
+        .boxed()
+    }
+
+    /// Streams matching alert logs oldest first, without loading them all into memory
+    pub fn stream_alert_logs<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+    ) -> BoxStream<'a, Result<AlertExportRow, AppError>> {
+        sqlx::query_as::<_, AlertExportRow>(
+            r#"
+            SELECT a.id, a.user_id, u.city, u.country, a.alert_type, a.message,
+                   a.channel, a.status, a.attempts, a.sent_at
+            FROM alert_logs a
+            JOIN users u ON u.id = a.user_id
+            WHERE ($1::text IS NULL OR LOWER(u.city) = LOWER($1))
+              AND ($2::text IS NULL OR UPPER(u.country) = UPPER($2))
+              AND ($3::timestamptz IS NULL OR a.sent_at >= $3)
+              AND ($4::timestamptz IS NULL OR a.sent_at < $4)
+            ORDER BY a.sent_at, a.id
+            "#,
+        )
+        .bind(&filter.city)
+        .bind(&filter.country)
+        .bind(filter.from)
+        .bind(filter.to)
+        .fetch(&self.pool)
+        .map_err(AppError::from)
+        .boxed()
+    }
+
+    /// Readings for `city` in `[from, to)` aggregated per `bucket`, oldest first. Buckets
+    /// without readings are left out.
+    pub async fn get_weather_buckets(
+        &self,
+        city: &str,
+        bucket: HistoryBucket,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<WeatherBucket>, AppError> {
+        let buckets = sqlx::query_as::<_, WeatherBucket>(
+            r#"
+            SELECT date_trunc($2, fetched_at, 'UTC') AS bucket_start,
+                   COUNT(*) AS readings,
+                   MIN(temperature) AS min_temperature,
+                   MAX(temperature) AS max_temperature,
+                   AVG(temperature) AS avg_temperature,
+                   MIN(humidity) AS min_humidity,
+                   MAX(humidity) AS max_humidity,
+                   AVG(humidity)::DOUBLE PRECISION AS avg_humidity,
+                   MIN(wind_speed) AS min_wind_speed,
+                   MAX(wind_speed) AS max_wind_speed,
+                   AVG(wind_speed) AS avg_wind_speed,
+                   mode() WITHIN GROUP (ORDER BY conditions) AS conditions
+            FROM weather_data
+            WHERE LOWER(city) = LOWER($1)
+              AND fetched_at >= $3
+              AND fetched_at < $4
+            GROUP BY bucket_start
+            ORDER BY bucket_start
+            "#,
+        )
+        .bind(city)
+        .bind(bucket.as_str())
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(buckets)
+    }
+
+    // Job runs
+    pub async fn create_job_run(
+        &self,
+        kind: &str,
+        source: &str,
+        actor: Option<&str>,
+    ) -> Result<JobRun, AppError> {
+        let run = sqlx::query_as::<_, JobRun>(
+            r#"
+            INSERT INTO job_runs (kind, source, actor)
+            VALUES ($1, $2, $3)
+            RETURNING *
+            "#,
+        )
+        .bind(kind)
+        .bind(source)
+        .bind(actor)
+        .fetch_one(&self.pool)
+        .await?;
+
+        info!("🏃 Job run {} started ({} via {})", run.id, kind, source);
+        Ok(run)
+    }
+
+    pub async fn set_job_cities_total(&self, id: Uuid, total: i32) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE job_runs SET cities_total = $2, updated_at = NOW() WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(total)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Adds the outcome of one city to the run's counters
+    pub async fn record_job_city(
+        &self,
+        id: Uuid,
+        succeeded: bool,
+        alerts_queued: i32,
+        alerts_deferred: i32,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE job_runs
+            SET cities_succeeded = cities_succeeded + CASE WHEN $2 THEN 1 ELSE 0 END,
+                cities_failed = cities_failed + CASE WHEN $2 THEN 0 ELSE 1 END,
+                alerts_queued = alerts_queued + $3,
+                alerts_deferred = alerts_deferred + $4,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(succeeded)
+        .bind(alerts_queued)
+        .bind(alerts_deferred)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks the run `succeeded`, or `failed` with `error`
+    pub async fn finish_job_run(&self, id: Uuid, error: Option<&str>) -> Result<JobRun, AppError> {
+        let run = sqlx::query_as::<_, JobRun>(
+            r#"
+            UPDATE job_runs
+            SET status = CASE WHEN $2::text IS NULL THEN 'succeeded' ELSE 'failed' END,
+                error = $2,
+                finished_at = NOW(),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(run)
+    }
+
+    /// Fails `running` runs without progress for `idle_minutes`, which were interrupted,
+    /// e.g. by a restart. Every processed city updates a run, so live runs are never idle
+    /// that long.
+    pub async fn fail_stale_job_runs(&self, idle_minutes: i64) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE job_runs
+            SET status = 'failed',
+                error = 'Interrupted: no progress for ' || $1 || ' minutes',
+                finished_at = NOW(),
+                updated_at = NOW()
+            WHERE status = 'running'
+              AND updated_at < NOW() - make_interval(mins => $1::INTEGER)
+            "#,
+        )
+        .bind(idle_minutes)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_job_run(&self, id: Uuid) -> Result<Option<JobRun>, AppError> {
+        let run = sqlx::query_as::<_, JobRun>(
+            r#"
+            SELECT * FROM job_runs WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(run)
+    }
+
+    pub async fn get_job_runs(&self, limit: i64) -> Result<Vec<JobRun>, AppError> {
+        let runs = sqlx::query_as::<_, JobRun>(
+            r#"
+            SELECT * FROM job_runs
+            ORDER BY started_at DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    // Alert logs
+
+    /// Records an alert and queues `notification` for it in one transaction, so an alert
+    /// is never left `queued` without an outbox message to deliver it.
+    pub async fn log_alert(
+        &self,
+        user: &User,
+        alert_type: &str,
+        message: &str,
+        channel: &str,
+        notification: &Notification,
+        max_attempts: i32,
+    ) -> Result<(AlertLog, OutboxMessage), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let alert = sqlx::query_as::<_, AlertLog>(
+            r#"
+            INSERT INTO alert_logs (user_id, alert_type, message, channel, status, attempts)
+            VALUES ($1, $2, $3, $4, 'queued', 0)
+            RETURNING *
+            "#,
+        )
+        .bind(user.id)
+        .bind(alert_type)
+        .bind(message)
+        .bind(channel)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let outbox_message = sqlx::query_as::<_, OutboxMessage>(
+            r#"
+            INSERT INTO outbox (user_id, alert_log_id, kind, recipient, payload, max_attempts)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(user.id)
+        .bind(alert.id)
+        .bind(notification.kind())
+        .bind(&user.email)
+        .bind(sqlx::types::Json(notification))
+        .bind(max_attempts)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok((alert, outbox_message))
+    }
+
+    /// Records an alert that was held back instead of being sent. Deferred alerts are
+    /// later folded into a digest by `enqueue_digest`.
+    pub async fn log_deferred_alert(
+        &self,
+        user_id: Uuid,
+        alert_type: &str,
+        message: &str,
+        channel: &str,
+        reason: &str,
+    ) -> Result<AlertLog, AppError> {
+        let alert = sqlx::query_as::<_, AlertLog>(
+            r#"
+            INSERT INTO alert_logs (user_id, alert_type, message, channel, status, attempts, status_reason)
+            VALUES ($1, $2, $3, $4, 'deferred', 0, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(alert_type)
+        .bind(message)
+        .bind(channel)
+        .bind(reason)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(alert)
+    }
+
+    /// Number of alerts sent (or on their way) to a user in the last `hours` hours.
+    /// Deferred and digested alerts don't count.
+    pub async fn count_recent_alerts(&self, user_id: Uuid, hours: i32) -> Result<i64, AppError> {
+        let count: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM alert_logs
+            WHERE user_id = $1
+              AND sent_at > NOW() - make_interval(hours => $2)
+              AND status NOT IN ('deferred', 'digested')
+            "#,
+        )
+        .bind(user_id)
+        .bind(hours)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Active users with deferred alerts. Alerts of pending or paused users stay deferred
+    /// until they are active again.
+    pub async fn get_users_with_deferred_alerts(&self) -> Result<Vec<User>, AppError> {
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT * FROM users u
+            WHERE u.status = 'active'
+              AND EXISTS (
+                  SELECT 1 FROM alert_logs a
+                  WHERE a.user_id = u.id AND a.status = 'deferred'
+              )
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    /// A user's deferred alerts, oldest first
+    pub async fn get_deferred_alerts(&self, user_id: Uuid) -> Result<Vec<AlertLog>, AppError> {
+        let alerts = sqlx::query_as::<_, AlertLog>(
+            r#"
+            SELECT * FROM alert_logs
+            WHERE user_id = $1 AND status = 'deferred'
+            ORDER BY sent_at ASC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(alerts)
+    }
+
+    /// Marks the user's deferred alerts as digested and queues a digest of them in one
+    /// transaction, so alerts are never marked without being sent. Returns `None` if the
+    /// user had no deferred alerts.
+    pub async fn enqueue_digest(
+        &self,
+        user: &User,
+        max_attempts: i32,
+    ) -> Result<Option<OutboxMessage>, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut alerts = sqlx::query_as::<_, AlertLog>(
+            r#"
+            UPDATE alert_logs
+            SET status = 'digested',
+                status_reason = 'Folded into daily digest',
+                updated_at = NOW()
+            WHERE user_id = $1 AND status = 'deferred'
+            RETURNING *
+            "#,
+        )
+        .bind(user.id)
+        .fetch_all(&mut *tx)
+        .await?;
+        if alerts.is_empty() {
+            return Ok(None);
+        }
+
+        // RETURNING has no ORDER BY
+        alerts.sort_by_key(|alert| alert.sent_at);
+        let notification = Notification::Digest {
+            city: user.city.clone(),
+            alerts: alerts.into_iter().map(DigestEntry::from).collect(),
+            locale: user.locale.clone(),
+        };
+
+        let message = sqlx::query_as::<_, OutboxMessage>(
+            r#"
+            INSERT INTO outbox (user_id, kind, recipient, payload, max_attempts)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(user.id)
+        .bind(notification.kind())
+        .bind(&user.email)
+        .bind(sqlx::types::Json(&notification))
+        .bind(max_attempts)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(message))
+    }
+
+    pub async fn update_alert_delivery(
+        &self,
+        alert_id: Uuid,
+        status: &str,
+        attempts: i32,
+        last_error: Option<&str>,
+        provider_message_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE alert_logs
+            SET status = $2,
+                attempts = $3,
+                last_error = $4,
+                provider_message_id = COALESCE($5, provider_message_id),
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(alert_id)
+        .bind(status)
+        .bind(attempts)
+        .bind(last_error)
+        .bind(provider_message_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every alert logged for a user, oldest first
+    pub async fn get_user_alert_history(&self, user_id: Uuid) -> Result<Vec<AlertLog>, AppError> {
+        let alerts = sqlx::query_as::<_, AlertLog>(
+            r#"
+            SELECT * FROM alert_logs
+            WHERE user_id = $1
+            ORDER BY sent_at, id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(alerts)
+    }
+
+    pub async fn get_user_alerts(
+        &self,
+        user_id: Uuid,
+        filter: &AlertFilter,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Page<AlertLog>, AppError> {
+        self.query_alerts(Some(user_id), filter, after, limit).await
+    }
+
+    pub async fn get_all_alerts(
+        &self,
+        filter: &AlertFilter,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Page<AlertLog>, AppError> {
+        self.query_alerts(None, filter, after, limit).await
+    }
+
+    /// One page of alerts (of one user, or of everyone), newest first, logged in `[from, to)`
+    async fn query_alerts(
+        &self,
+        user_id: Option<Uuid>,
+        filter: &AlertFilter,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Page<AlertLog>, AppError> {
+        let alerts = sqlx::query_as::<_, AlertLog>(
+            r#"
+            SELECT * FROM alert_logs
+            WHERE ($1::uuid IS NULL OR user_id = $1)
+              AND ($2::text[] IS NULL OR alert_type = ANY($2))
+              AND ($3::timestamptz IS NULL OR sent_at >= $3)
+              AND ($4::timestamptz IS NULL OR sent_at < $4)
+              AND ($5::timestamptz IS NULL OR (sent_at, id) < ($5, $6))
+            ORDER BY sent_at DESC, id DESC
+            LIMIT $7
+            "#,
+        )
+        .bind(user_id)
+        .bind(&filter.alert_types)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(after.map(|c| c.at))
+        .bind(after.map(|c| c.id))
+        .bind(limit + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Page::from_rows(alerts, limit, |a| Cursor {
+            at: a.sent_at,
+            id: a.id,
+        }))
+    }
+
+    /// The `per_user` most recent alerts of each of `user_ids` in one query, newest first
+    pub async fn get_recent_alerts_for_users(
+        &self,
+        user_ids: &[Uuid],
+        per_user: i64,
+    ) -> Result<Vec<AlertLog>, AppError> {
+        let alerts = sqlx::query_as::<_, AlertLog>(
+            r#"
+            SELECT * FROM (
+                SELECT *, ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY sent_at DESC, id DESC) AS rank
+                FROM alert_logs
+                WHERE user_id = ANY($1)
+            ) ranked
+            WHERE rank <= $2
+            ORDER BY sent_at DESC, id DESC
+            "#,
+        )
+        .bind(user_ids)
+        .bind(per_user)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(alerts)
+    }
+
+    // Notification outbox
+    pub async fn enqueue_notification(
+        &self,
+        user_id: Option<Uuid>,
+        alert_log_id: Option<Uuid>,
+        recipient: &str,
+        notification: &Notification,
+        max_attempts: i32,
+    ) -> Result<OutboxMessage, AppError> {
+        let message = sqlx::query_as::<_, OutboxMessage>(
+            r#"
+            INSERT INTO outbox (user_id, alert_log_id, kind, recipient, payload, max_attempts)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(alert_log_id)
+        .bind(notification.kind())
+        .bind(recipient)
+        .bind(sqlx::types::Json(notification))
+        .bind(max_attempts)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(message)
+    }
+
+    /// Claims up to `limit` due messages for delivery. Rows claimed by a worker that died
+    /// mid-send become claimable again once their lease expires.
+    pub async fn claim_outbox_batch(
+        &self,
+        limit: i64,
+        lease_secs: f64,
+    ) -> Result<Vec<OutboxMessage>, AppError> {
+        let messages = sqlx::query_as::<_, OutboxMessage>(
+            r#"
+            UPDATE outbox
+            SET status = 'processing',
+                locked_until = NOW() + make_interval(secs => $2),
+                updated_at = NOW()
+            WHERE id IN (
+                SELECT id FROM outbox
+                WHERE (status = 'pending' AND next_attempt_at <= NOW())
+                   OR (status = 'processing' AND locked_until < NOW())
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(limit)
+        .bind(lease_secs)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(messages)
+    }
+
+    /// Whether any message could be claimed right now
+    pub async fn has_due_outbox_messages(&self) -> Result<bool, AppError> {
+        let due: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM outbox
+                WHERE (status = 'pending' AND next_attempt_at <= NOW())
+                   OR (status = 'processing' AND locked_until < NOW())
+            )
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(due)
+    }
+
+    /// Number of messages delivered in the last `secs` seconds, across all workers
+    pub async fn count_sent_since(&self, secs: f64) -> Result<i64, AppError> {
+        let count: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM outbox
+            WHERE status = 'sent' AND updated_at > NOW() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(secs)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// When the earliest message delivered in the last `secs` seconds was sent
+    pub async fn oldest_sent_since(&self, secs: f64) -> Result<Option<DateTime<Utc>>, AppError> {
+        let oldest: Option<DateTime<Utc>> = sqlx::query_scalar(
+            r#"
+            SELECT MIN(updated_at) FROM outbox
+            WHERE status = 'sent' AND updated_at > NOW() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(secs)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(oldest)
+    }
+
+    pub async fn mark_outbox_sent(&self, id: Uuid) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE outbox
+            SET status = 'sent',
+                attempts = attempts + 1,
+                locked_until = NULL,
+                last_error = NULL,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt. `retry_at` schedules the next attempt; `None` dead-letters
+    /// the message.
+    pub async fn mark_outbox_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE outbox
+            SET status = CASE WHEN $3::TIMESTAMPTZ IS NULL THEN 'dead' ELSE 'pending' END,
+                attempts = attempts + 1,
+                next_attempt_at = COALESCE($3, next_attempt_at),
+                locked_until = NULL,
+                last_error = $2,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(retry_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn mark_outbox_suppressed(&self, id: Uuid, reason: &str) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE outbox
+            SET status = 'suppressed',
+                locked_until = NULL,
+                last_error = $2,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_outbox_messages(
+        &self,
+        status: &str,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, AppError> {
+        let messages = sqlx::query_as::<_, OutboxMessage>(
+            r#"
+            SELECT * FROM outbox
+            WHERE status = $1
+            ORDER BY updated_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(messages)
+    }
+
+    pub async fn requeue_dead_message(&self, id: Uuid) -> Result<Option<OutboxMessage>, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let message = sqlx::query_as::<_, OutboxMessage>(
+            r#"
+            UPDATE outbox
+            SET status = 'pending',
+                attempts = 0,
+                next_attempt_at = NOW(),
+                last_error = NULL,
+                updated_at = NOW()
+            WHERE id = $1 AND status = 'dead'
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        // The alert is back to waiting for delivery as well
+        if let Some(alert_log_id) = message.as_ref().and_then(|m| m.alert_log_id) {
+            sqlx::query(
+                r#"
+                UPDATE alert_logs
+                SET status = 'queued', attempts = 0, last_error = NULL, updated_at = NOW()
+                WHERE id = $1
+                "#,
+            )
+            .bind(alert_log_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(message)
+    }
+
+    pub async fn mark_alert_bounced(&self, provider_message_id: &str, reason: &str) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE alert_logs
+            SET status = 'bounced', last_error = $2, updated_at = NOW()
+            WHERE provider_message_id = $1
+            "#,
+        )
+        .bind(provider_message_id)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Suppression list
+    pub async fn get_suppression(&self, email: &str) -> Result<Option<Suppression>, AppError> {
+        let suppression = sqlx::query_as::<_, Suppression>(
+            r#"
+            SELECT * FROM suppressions WHERE email = LOWER($1)
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(suppression)
+    }
+
+    pub async fn is_suppressed(&self, email: &str) -> Result<bool, AppError> {
+        let suppressed: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM suppressions WHERE email = LOWER($1))
+            "#,
+        )
+        .bind(email)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(suppressed)
+    }
+
+    pub async fn suppress_email(
+        &self,
+        email: &str,
+        reason: &str,
+        details: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO suppressions (email, reason, details)
+            VALUES (LOWER($1), $2, $3)
+            ON CONFLICT (email) DO NOTHING
+            "#,
+        )
+        .bind(email)
+        .bind(reason)
+        .bind(details)
+        .execute(&self.pool)
+        .await?;
+
+        info!("🚫 Suppressed {} ({})", email, reason);
+        Ok(())
+    }
+
+    pub async fn get_suppressions(&self, limit: i64) -> Result<Vec<Suppression>, AppError> {
+        let suppressions = sqlx::query_as::<_, Suppression>(
+            r#"
+            SELECT * FROM suppressions
+            ORDER BY created_at DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(suppressions)
+    }
+
+    /// Lifts a suppression and forgets past failures. Returns whether one existed.
+    pub async fn remove_suppression(&self, email: &str) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM suppressions WHERE email = LOWER($1)
+            "#,
+        )
+        .bind(email)
+        .execute(&self.pool)
+        .await?;
+
+        self.reset_email_failures(email).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Counts a permanent delivery failure and returns the running total for the address
+    pub async fn record_permanent_failure(&self, email: &str, error: &str) -> Result<i32, AppError> {
+        let failures: i32 = sqlx::query_scalar(
+            r#"
+            INSERT INTO email_failures (email, permanent_failures, last_error)
+            VALUES (LOWER($1), 1, $2)
+            ON CONFLICT (email) DO UPDATE
+            SET permanent_failures = email_failures.permanent_failures + 1,
+                last_error = EXCLUDED.last_error,
+                updated_at = NOW()
+            RETURNING permanent_failures
+            "#,
+        )
+        .bind(email)
+        .bind(error)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(failures)
+    }
+
+    pub async fn reset_email_failures(&self, email: &str) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            DELETE FROM email_failures WHERE email = LOWER($1)
+            "#,
+        )
+        .bind(email)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
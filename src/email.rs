@@ -1,201 +1,503 @@
+use crate::chart::TemperatureChart;
+use crate::config::{Config, MailTransport, SmtpTls};
 use crate::error::AppError;
+use crate::i18n;
+use crate::models::DigestEntry;
+use crate::templates::{RenderedEmail, Templates};
+use crate::tokens::{TokenPurpose, TokenSigner};
+use lettre::message::header::{HeaderName, HeaderValue};
 use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
 use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
+use lettre::transport::smtp::PoolConfig;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 use log::info;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
 
+/// Per-recipient links embedded in emails so users can opt out without logging in
+#[derive(Debug, Clone)]
+pub struct SubscriptionLinks {
+    pub unsubscribe_url: String,
+    pub preferences_url: String,
+}
+
+// Content-ID of the temperature chart attached to alert emails
+pub const CHART_CID: &str = "temperature-chart";
+
+/// Everything that goes into one notification, built without sending anything. The same
+/// content backs real emails and admin previews.
+pub struct ComposedEmail {
+    pub subject: String,
+    pub body: RenderedEmail,
+    /// Short Markdown version for chat channels, if the notification has a chat template
+    pub chat: Option<String>,
+    pub links: Option<SubscriptionLinks>,
+    /// Chart attached inline and referenced from the HTML as `cid:CHART_CID`
+    pub chart: Option<TemperatureChart>,
+}
+
+/// Builds notification content from templates: subject, HTML, plain text and chat text,
+/// plus the signed links that go into it.
+#[derive(Clone)]
+pub struct EmailComposer {
+    templates: Arc<Templates>,
+    signer: TokenSigner,
+    public_base_url: String,
+    token_ttl: chrono::Duration,
+    verification_ttl: chrono::Duration,
+    login_ttl: chrono::Duration,
+}
+
+impl EmailComposer {
+    pub fn new(config: &Config) -> Result<Self, AppError> {
+        Ok(Self {
+            templates: Arc::new(Templates::load(&config.templates_dir)?),
+            signer: TokenSigner::new(&config.app_secret),
+            public_base_url: config.public_base_url.clone(),
+            token_ttl: chrono::Duration::days(config.subscription_token_ttl_days),
+            verification_ttl: chrono::Duration::hours(config.pending_user_ttl_hours),
+            login_ttl: chrono::Duration::minutes(config.login_link_ttl_minutes),
+        })
+    }
+
+    pub fn subscription_links(&self, user_id: Uuid) -> SubscriptionLinks {
+        let unsubscribe = self.signer.sign(user_id, TokenPurpose::Unsubscribe, self.token_ttl);
+        let preferences = self.signer.sign(user_id, TokenPurpose::Preferences, self.token_ttl);
+        SubscriptionLinks {
+            unsubscribe_url: format!(
+                "{}/api/subscription/unsubscribe?token={}",
+                self.public_base_url, unsubscribe
+            ),
+            preferences_url: format!(
+                "{}/api/subscription?token={}",
+                self.public_base_url, preferences
+            ),
+        }
+    }
+
+    pub fn weather_alert(
+        &self,
+        user_id: Option<Uuid>,
+        city: &str,
+        alert_message: &str,
+        locale: &str,
+        chart: Option<TemperatureChart>,
+    ) -> Result<ComposedEmail, AppError> {
+        let links = user_id.map(|id| self.subscription_links(id));
+        let ctx = serde_json::json!({
+            "city": city,
+            "alert_message": alert_message,
+            "locale": locale,
+            "chart_cid": chart.as_ref().map(|_| CHART_CID),
+            "chart_low": chart.as_ref().map(|c| format!("{:.1}", c.low)),
+            "chart_high": chart.as_ref().map(|c| format!("{:.1}", c.high)),
+            "unsubscribe_url": links.as_ref().map(|l| &l.unsubscribe_url),
+            "preferences_url": links.as_ref().map(|l| &l.preferences_url),
+        });
+
+        Ok(ComposedEmail {
+            subject: i18n::t(locale, "email.alert.subject", &[("city", city)]),
+            body: self.templates.render("alert", &ctx)?,
+            chat: self.templates.render_chat("alert", &ctx)?,
+            links,
+            chart,
+        })
+    }
+
+    pub fn welcome(
+        &self,
+        user_id: Option<Uuid>,
+        city: &str,
+        locale: &str,
+    ) -> Result<ComposedEmail, AppError> {
+        let links = user_id.map(|id| self.subscription_links(id));
+        let ctx = serde_json::json!({
+            "city": city,
+            "locale": locale,
+            "unsubscribe_url": links.as_ref().map(|l| &l.unsubscribe_url),
+            "preferences_url": links.as_ref().map(|l| &l.preferences_url),
+        });
+
+        Ok(ComposedEmail {
+            subject: i18n::t(locale, "email.welcome.subject", &[]),
+            body: self.templates.render("welcome", &ctx)?,
+            chat: self.templates.render_chat("welcome", &ctx)?,
+            links,
+            chart: None,
+        })
+    }
+
+    pub fn verification(
+        &self,
+        user_id: Uuid,
+        email: &str,
+        city: &str,
+        locale: &str,
+    ) -> Result<ComposedEmail, AppError> {
+        let token = self.signer.sign_for_address(
+            user_id,
+            email,
+            TokenPurpose::Verify,
+            self.verification_ttl,
+        );
+        let verify_url = format!("{}/api/users/verify?token={}", self.public_base_url, token);
+        let ctx = serde_json::json!({
+            "city": city,
+            "locale": locale,
+            "verify_url": verify_url,
+            "expires_in_hours": self.verification_ttl.num_hours(),
+        });
+
+        Ok(ComposedEmail {
+            subject: i18n::t(locale, "email.verify.subject", &[]),
+            body: self.templates.render("verify", &ctx)?,
+            chat: self.templates.render_chat("verify", &ctx)?,
+            links: None,
+            chart: None,
+        })
+    }
+
+    pub fn login_link(&self, user_id: Uuid, locale: &str) -> Result<ComposedEmail, AppError> {
+        let token = self.signer.sign(user_id, TokenPurpose::Login, self.login_ttl);
+        let login_url = format!("{}/api/auth/verify?token={}", self.public_base_url, token);
+        let ctx = serde_json::json!({
+            "locale": locale,
+            "login_url": login_url,
+            "expires_in_minutes": self.login_ttl.num_minutes(),
+        });
+
+        Ok(ComposedEmail {
+            subject: i18n::t(locale, "email.login.subject", &[]),
+            body: self.templates.render("login", &ctx)?,
+            chat: None,
+            links: None,
+            chart: None,
+        })
+    }
+
+    pub fn digest(
+        &self,
+        user_id: Option<Uuid>,
+        city: &str,
+        alerts: &[DigestEntry],
+        locale: &str,
+    ) -> Result<ComposedEmail, AppError> {
+        let links = user_id.map(|id| self.subscription_links(id));
+        let ctx = serde_json::json!({
+            "city": city,
+            "locale": locale,
+            "alerts": alerts
+                .iter()
+                .map(|alert| serde_json::json!({
+                    "message": alert.message,
+                    "time": alert.logged_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                }))
+                .collect::<Vec<_>>(),
+            "unsubscribe_url": links.as_ref().map(|l| &l.unsubscribe_url),
+            "preferences_url": links.as_ref().map(|l| &l.preferences_url),
+        });
+
+        Ok(ComposedEmail {
+            subject: i18n::t(locale, "email.digest.subject", &[("city", city)]),
+            body: self.templates.render("digest", &ctx)?,
+            chat: self.templates.render_chat("digest", &ctx)?,
+            links,
+            chart: None,
+        })
+    }
+
+    pub fn test(&self, to: &str, subject: &str) -> Result<ComposedEmail, AppError> {
+        let ctx = serde_json::json!({
+            "recipient": to,
+            "subject": subject,
+            "sent_at": chrono::Utc::now().to_rfc3339(),
+        });
+
+        Ok(ComposedEmail {
+            subject: subject.to_string(),
+            body: self.templates.render("test", &ctx)?,
+            chat: None,
+            links: None,
+            chart: None,
+        })
+    }
+}
+
+/// Where built messages end up
+#[derive(Clone)]
+enum Transport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>),
+    Stdout,
+}
 
 #[derive(Clone)]
 pub struct EmailClient {
-    smtp_transport: std::sync::Arc<SmtpTransport>,
-    from_email: String,
+    transport: Transport,
+    from: Mailbox,
+    reply_to: Option<Mailbox>,
+    message_id_domain: String,
+    // Caps the number of messages in flight across all clones of the client
+    send_permits: Arc<Semaphore>,
+    composer: EmailComposer,
 }
 
 impl EmailClient {
-    pub fn new(
-        smtp_host: &str,
-        smtp_port: u16,
-        username: &str,
-        password: &str,
-    ) -> Result<Self, AppError> {
-        info!("📧 Initializing email client: {}:{}", smtp_host, smtp_port);
+    pub fn new(config: &Config) -> Result<Self, AppError> {
+        let pool = PoolConfig::new().max_size(config.smtp_pool_size.max(1));
 
-        let creds = Credentials::new(username.to_string(), password.to_string());
+        // The async SMTP transports keep connections open and reuse them across sends
+        let transport = match config.mail_transport {
+            MailTransport::Smtp => {
+                info!(
+                    "📧 Initializing email client: {}:{} ({:?} TLS, pool size {}, concurrency {})",
+                    config.smtp_host,
+                    config.smtp_port,
+                    config.smtp_tls,
+                    config.smtp_pool_size,
+                    config.email_concurrency
+                );
+                let builder = match config.smtp_tls {
+                    SmtpTls::None => {
+                        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+                    }
+                    SmtpTls::Starttls => {
+                        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+                    }
+                    SmtpTls::Implicit => {
+                        AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?
+                    }
+                };
+                let builder = builder.port(config.smtp_port);
+                // Credentials are never sent over a plaintext connection
+                let builder = match (&config.smtp_username, &config.smtp_password, config.smtp_tls) {
+                    (Some(username), Some(password), SmtpTls::Starttls | SmtpTls::Implicit) => {
+                        builder.credentials(Credentials::new(username.clone(), password.clone()))
+                    }
+                    _ => builder,
+                };
+                Transport::Smtp(
+                    builder
+                        .pool_config(pool)
+                        .build(),
+                )
+            }
+            MailTransport::SmtpLocal => {
+                info!(
+                    "📧 Initializing email client: local SMTP {}:{} (no TLS, no auth)",
+                    config.smtp_host, config.smtp_port
+                );
+                Transport::Smtp(
+                    AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+                        .port(config.smtp_port)
+                        .pool_config(pool)
+                        .build(),
+                )
+            }
+            MailTransport::File => {
+                info!("📧 Initializing email client: writing .eml files to {}", config.mail_file_dir);
+                std::fs::create_dir_all(&config.mail_file_dir)?;
+                Transport::File(AsyncFileTransport::<Tokio1Executor>::new(&config.mail_file_dir))
+            }
+            MailTransport::Stdout => {
+                info!("📧 Initializing email client: printing messages to stdout");
+                Transport::Stdout
+            }
+        };
 
-        let transport = SmtpTransport::relay(smtp_host)?
-        .port(smtp_port)
-        .credentials(creds)
-        .build();
+        let from_address = config
+            .mail_from
+            .parse()
+            .map_err(|e| AppError::Config(format!("Invalid MAIL_FROM '{}': {}", config.mail_from, e)))?;
+        let reply_to = config
+            .mail_reply_to
+            .as_deref()
+            .map(|reply_to| {
+                reply_to
+                    .parse()
+                    .map_err(|e| AppError::Config(format!("Invalid MAIL_REPLY_TO '{}': {}", reply_to, e)))
+            })
+            .transpose()?;
+        let message_id_domain = config.message_id_domain.clone().unwrap_or_else(|| {
+            config
+                .mail_from
+                .rsplit_once('@')
+                .map(|(_, domain)| domain.to_string())
+                .unwrap_or_else(|| "localhost".to_string())
+        });
 
         Ok(Self {
-            // Wrap in Arc for cloning
-            smtp_transport: std::sync::Arc::new(transport),
-            from_email: username.to_string(),
+            transport,
+            from: Mailbox::new(config.mail_from_name.clone(), from_address),
+            reply_to,
+            message_id_domain,
+            send_permits: Arc::new(Semaphore::new(config.email_concurrency.max(1))),
+            composer: EmailComposer::new(config)?,
         })
     }
 
+    pub fn composer(&self) -> &EmailComposer {
+        &self.composer
+    }
+
     pub async fn send_weather_alert(
         &self,
         to: &str,
+        user_id: Option<Uuid>,
         city: &str,
         alert_message: &str,
-    ) -> Result<(), AppError> {
-        let subject = format!("⚠️ Weather Alert for {}", city);
-        
-        let body = format!(
-            r#"
-            <html>
-            <head>
-                <style>
-                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
-                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
-                    .header {{ background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); 
-                              color: white; padding: 30px; border-radius: 10px 10px 0 0; }}
-                    .content {{ background: #f4f4f4; padding: 30px; border-radius: 0 0 10px 10px; }}
-                    .alert-box {{ background: #fff3cd; border-left: 4px solid #ffc107; 
-                                  padding: 15px; margin: 20px 0; border-radius: 5px; }}
-                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
-                </style>
-            </head>
-            <body>
-                <div class="container">
-                    <div class="header">
-                        <h1>🌤️ Weather Alert System</h1>
-                        <p>Your personalized weather notification</p>
-                    </div>
-                    <div class="content">
-                        <h2>Alert for {}</h2>
-                        <div class="alert-box">
-                            <strong>Alert Message:</strong><br/>
-                            {}
-                        </div>
-                        <p>This alert was triggered based on your weather preferences.</p>
-                        <p><strong>What to do?</strong></p>
-                        <ul>
-                            <li>Check the current conditions</li>
-                            <li>Plan accordingly for your day</li>
-                            <li>Update your preferences if needed</li>
-                        </ul>
-                    </div>
-                    <div class="footer">
-                        <p>Weather Alert System - Powered by OpenWeatherMap</p>
-                        <p>To update your preferences, visit your dashboard</p>
-                    </div>
-                </div>
-            </body>
-            </html>
-            "#,
-            city, alert_message
-        );
+        locale: &str,
+        chart: Option<TemperatureChart>,
+    ) -> Result<String, AppError> {
+        let email = self
+            .composer
+            .weather_alert(user_id, city, alert_message, locale, chart)?;
+        self.send_email(to, email).await
+    }
 
-        self.send_email(to, &subject, &body).await
-    }
-
-    pub async fn send_welcome_email(&self, to: &str, city: &str) -> Result<(), AppError> {
-        let subject = "Welcome to Weather Alert System! 🌤️";
-        
-        let body = format!(
-            r#"
-            <html>
-            <head>
-                <style>
-                    body {{ font-family: Arial, sans-serif; line-height: 1.6; color: #333; }}
-                    .container {{ max-width: 600px; margin: 0 auto; padding: 20px; }}
-                    .header {{ background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); 
-                              color: white; padding: 30px; border-radius: 10px 10px 0 0; text-align: center; }}
-                    .content {{ background: #f4f4f4; padding: 30px; border-radius: 0 0 10px 10px; }}
-                    .button {{ display: inline-block; padding: 12px 30px; background: #667eea; 
-                              color: white; text-decoration: none; border-radius: 5px; margin-top: 20px; }}
-                    .footer {{ text-align: center; margin-top: 20px; color: #666; font-size: 12px; }}
-                </style>
-            </head>
-            <body>
-                <div class="container">
-                    <div class="header">
-                        <h1>🎉 Welcome!</h1>
-                        <p>You're now registered for weather alerts</p>
-                    </div>
-                    <div class="content">
-                        <h2>Hi there! 👋</h2>
-                        <p>Thank you for registering with Weather Alert System!</p>
-                        <p><strong>Your Location:</strong> {}</p>
-                        <p>We'll monitor the weather in your area and send you alerts based on your preferences.</p>
-                        
-                        <h3>What's Next?</h3>
-                        <ul>
-                            <li>Set your temperature thresholds (min/max)</li>
-                            <li>Choose weather conditions to be alerted about (rain, snow, storms)</li>
-                            <li>Receive automatic alerts every 2 hours</li>
-                        </ul>
-                        
-                        <p>Our CRON job runs every 2 hours to check weather conditions and send alerts.</p>
-                    </div>
-                    <div class="footer">
-                        <p>Weather Alert System - Stay informed, stay prepared</p>
-                    </div>
-                </div>
-            </body>
-            </html>
-            "#,
-            city
-        );
+    pub async fn send_welcome_email(
+        &self,
+        to: &str,
+        user_id: Option<Uuid>,
+        city: &str,
+        locale: &str,
+    ) -> Result<String, AppError> {
+        let email = self.composer.welcome(user_id, city, locale)?;
+        self.send_email(to, email).await
+    }
 
-        self.send_email(to, subject, &body).await
-    }
-
-    pub async fn send_test_email(&self, to: &str, subject: &str) -> Result<(), AppError> {
-        let body = format!(
-            r#"
-            <html>
-            <body style="font-family: Arial, sans-serif; padding: 20px;">
-                <h2>✅ Email Configuration Test</h2>
-                <p>If you're reading this, your email configuration is working correctly!</p>
-                <p><strong>Test Details:</strong></p>
-                <ul>
-                    <li>Recipient: {}</li>
-                    <li>Subject: {}</li>
-                    <li>Time: {}</li>
-                </ul>
-                <p>Your Weather Alert System is ready to send notifications.</p>
-            </body>
-            </html>
-            "#,
-            to,
-            subject,
-            chrono::Utc::now().to_rfc3339()
-        );
+    pub async fn send_verification_email(
+        &self,
+        to: &str,
+        user_id: Uuid,
+        city: &str,
+        locale: &str,
+    ) -> Result<String, AppError> {
+        let email = self.composer.verification(user_id, to, city, locale)?;
+        self.send_email(to, email).await
+    }
 
-        self.send_email(to, subject, &body).await
+    pub async fn send_login_link(
+        &self,
+        to: &str,
+        user_id: Uuid,
+        locale: &str,
+    ) -> Result<String, AppError> {
+        let email = self.composer.login_link(user_id, locale)?;
+        self.send_email(to, email).await
     }
 
-    async fn send_email(&self, to: &str, subject: &str, html_body: &str) -> Result<(), AppError> {
-        let email = Message::builder()
-            .from(
-                self.from_email
-                    .parse()
-                    .map_err(|e| AppError::Email(format!("Invalid from address: {}", e)))?,
-            )
+    pub async fn send_digest(
+        &self,
+        to: &str,
+        user_id: Option<Uuid>,
+        city: &str,
+        alerts: &[DigestEntry],
+        locale: &str,
+    ) -> Result<String, AppError> {
+        let email = self.composer.digest(user_id, city, alerts, locale)?;
+        self.send_email(to, email).await
+    }
+
+    pub async fn send_test_email(&self, to: &str, subject: &str) -> Result<String, AppError> {
+        let email = self.composer.test(to, subject)?;
+        self.send_email(to, email).await
+    }
+
+    /// Sends one message and returns the Message-ID it was sent with, which is how later
+    /// provider events (bounces, complaints) can be matched back to it. With subscription
+    /// links, the message carries RFC 8058 one-click unsubscribe headers; with a chart, the
+    /// HTML part is wrapped in `multipart/related` together with the image.
+    async fn send_email(&self, to: &str, email: ComposedEmail) -> Result<String, AppError> {
+        let message_id = self.new_message_id();
+        let ComposedEmail {
+            subject,
+            body,
+            links,
+            chart,
+            ..
+        } = email;
+
+        let mut builder = Message::builder();
+        if let Some(links) = &links {
+            builder = builder
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                    format!("<{}>", links.unsubscribe_url),
+                ))
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ));
+        }
+
+        if let Some(reply_to) = &self.reply_to {
+            builder = builder.reply_to(reply_to.clone());
+        }
+
+        let email = builder
+            .message_id(Some(message_id.clone()))
+            .from(self.from.clone())
             .to(to
                 .parse()
                 .map_err(|e| AppError::Email(format!("Invalid to address: {}", e)))?)
             .subject(subject)
-            .header(ContentType::TEXT_HTML)
-            .body(html_body.to_string())
+            .multipart(match chart {
+                Some(chart) => {
+                    let png_type = ContentType::parse("image/png")
+                        .map_err(|e| AppError::Email(format!("Invalid content type: {}", e)))?;
+                    MultiPart::alternative()
+                        .singlepart(SinglePart::plain(body.text))
+                        .multipart(
+                            MultiPart::related()
+                                .singlepart(SinglePart::html(body.html))
+                                .singlepart(
+                                    Attachment::new_inline(CHART_CID.to_string())
+                                        .body(chart.png, png_type),
+                                ),
+                        )
+                }
+                None => MultiPart::alternative_plain_html(body.text, body.html),
+            })
             .map_err(|e| AppError::Email(format!("Failed to build email: {}", e)))?;
 
-        // Clone the transport Arc and message to move into the blocking task
-        let transport = self.smtp_transport.clone();
-        let email_to_send = email.clone();
+        let _permit = self
+            .send_permits
+            .acquire()
+            .await
+            .map_err(|e| AppError::Email(format!("Email client closed: {}", e)))?;
 
-        // Use spawn_blocking for synchronous I/O in an async function
-        tokio::task::spawn_blocking(move || {
-            transport.send(&email_to_send)
-        })
-        .await
-        .map_err(|e| AppError::Email(format!("Task spawn error: {}", e)))? // Handle task join error
-        .map_err(|e| AppError::Email(format!("Failed to send email: {}", e)))?; // Handle email sending error
+        match &self.transport {
+            // 5xx replies mean the server will never accept this message (unknown mailbox,
+            // policy rejection); callers use that to stop retrying and suppress the address
+            Transport::Smtp(transport) => {
+                transport.send(email).await.map_err(|e| {
+                    if e.is_permanent() {
+                        AppError::EmailRejected(e.to_string())
+                    } else {
+                        AppError::Email(format!("Failed to send email: {}", e))
+                    }
+                })?;
+            }
+            Transport::File(transport) => {
+                let file_id = transport
+                    .send(email)
+                    .await
+                    .map_err(|e| AppError::Email(format!("Failed to write email: {}", e)))?;
+                info!("📁 Email written to {}.eml", file_id);
+            }
+            Transport::Stdout => {
+                println!("{}", String::from_utf8_lossy(&email.formatted()));
+            }
+        }
+
+        info!("✅ Email sent to: {} ({})", to, message_id);
+        Ok(message_id)
+    }
 
-        info!("✅ Email sent to: {}", to);
-        Ok(())
+    fn new_message_id(&self) -> String {
+        format!("<{}@{}>", uuid::Uuid::new_v4(), self.message_id_domain)
     }
 }
\ No newline at end of file
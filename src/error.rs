@@ -1,6 +1,9 @@
 // ==================== error.rs ====================
+use actix_web::http::header::RETRY_AFTER;
 use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
+use serde::Serialize;
 use std::fmt;
+use utoipa::ToSchema;
 
 #[derive(Debug)]
 pub enum AppError {
@@ -8,11 +11,19 @@ pub enum AppError {
     Scheduler(tokio_cron_scheduler::JobSchedulerError),
     WeatherApi(String),
     Email(String),
+    EmailRejected(String),
     Config(String),
     NotFound(String),
     Conflict(String),
     Validation(String),
+    /// Well-formed but can't be processed, e.g. a reused idempotency key
+    Unprocessable(String),
+    Unauthorized(String),
+    Forbidden(String),
+    /// Too many requests; retry after this many seconds
+    RateLimited(u64),
     Internal(String),
+    Export(String),
     Io(std::io::Error),
 }
 
@@ -23,11 +34,17 @@ impl fmt::Display for AppError {
             AppError::Scheduler(e) => write!(f, "Scheduler error: {}", e),
             AppError::WeatherApi(e) => write!(f, "Weather API error: {}", e),
             AppError::Email(e) => write!(f, "Email error: {}", e),
+            AppError::EmailRejected(e) => write!(f, "Email permanently rejected: {}", e),
             AppError::Config(e) => write!(f, "Configuration error: {}", e),
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
             AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
             AppError::Validation(msg) => write!(f, "Validation error: {}", msg),
+            AppError::Unprocessable(msg) => write!(f, "Unprocessable: {}", msg),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            AppError::RateLimited(secs) => write!(f, "Too many requests, retry in {}s", secs),
             AppError::Internal(msg) => write!(f, "Internal error: {}", msg),
+            AppError::Export(msg) => write!(f, "Export error: {}", msg),
             AppError::Io(e) => write!(f, "IO error: {}", e),
         }
     }
@@ -35,19 +52,38 @@ impl fmt::Display for AppError {
 
 impl std::error::Error for AppError {}
 
+/// JSON body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    #[schema(example = false)]
+    pub success: bool,
+    pub error: String,
+}
+
 impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
             AppError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
             AppError::Validation(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::Unprocessable(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg.clone()),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
+            AppError::RateLimited(secs) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Too many requests, retry in {} seconds", secs),
+            ),
             _ => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
 
-        HttpResponse::build(status).json(serde_json::json!({
-            "success": false,
-            "error": message
-        }))
+        let mut response = HttpResponse::build(status);
+        if let AppError::RateLimited(secs) = self {
+            response.insert_header((RETRY_AFTER, secs.to_string()));
+        }
+        response.json(ErrorBody {
+            success: false,
+            error: message,
+        })
     }
 
     fn status_code(&self) -> StatusCode {
@@ -55,6 +91,10 @@ impl ResponseError for AppError {
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::Conflict(_) => StatusCode::CONFLICT,
             AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -78,6 +118,24 @@ impl From<std::io::Error> for AppError {
     }
 }
 
+impl From<csv::Error> for AppError {
+    fn from(err: csv::Error) -> Self {
+        AppError::Export(format!("CSV: {}", err))
+    }
+}
+
+impl From<parquet::errors::ParquetError> for AppError {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        AppError::Export(format!("Parquet: {}", err))
+    }
+}
+
+impl From<arrow_schema::ArrowError> for AppError {
+    fn from(err: arrow_schema::ArrowError) -> Self {
+        AppError::Export(format!("Arrow: {}", err))
+    }
+}
+
 impl From<lettre::transport::smtp::Error> for AppError {
     fn from(err: lettre::transport::smtp::Error) -> Self {
         AppError::Email(format!("SMTP error: {}", err))
@@ -0,0 +1,126 @@
+use crate::models::{AlertLog, WeatherData};
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream};
+use futures::StreamExt;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// Events kept for subscribers that fall behind before they start missing some
+const BUS_CAPACITY: usize = 256;
+// Comment lines sent on idle streams so proxies don't close them
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// An alert as pushed to its user, with the city it was raised for
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AlertEvent {
+    pub city: String,
+    pub alert: AlertLog,
+}
+
+#[derive(Debug, Clone)]
+pub enum LiveEvent {
+    /// A new observation, stored by a weather fetch
+    Weather(WeatherData),
+    /// An alert queued for delivery
+    Alert(AlertEvent),
+}
+
+impl LiveEvent {
+    fn city(&self) -> &str {
+        match self {
+            LiveEvent::Weather(weather) => &weather.city,
+            LiveEvent::Alert(event) => &event.city,
+        }
+    }
+
+    /// Server-Sent Events frame, named after the event type
+    fn to_sse(&self) -> String {
+        let (name, data) = match self {
+            LiveEvent::Weather(weather) => ("weather", serde_json::to_string(weather)),
+            LiveEvent::Alert(event) => ("alert", serde_json::to_string(event)),
+        };
+        format!("event: {}\ndata: {}\n\n", name, data.unwrap_or_default())
+    }
+}
+
+/// In-process fan-out of live events to the open event streams. Publishing never blocks
+/// and is a no-op while nobody listens, so fetches from the CLI can use a bus of their own.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: LiveEvent) {
+        // Only fails when there are no subscribers
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Which events a stream receives: observations of `city` (or of every city), and the
+/// alerts of `user_id` if the stream is authenticated
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    pub city: Option<String>,
+    pub user_id: Option<Uuid>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &LiveEvent) -> bool {
+        let city_matches = self
+            .city
+            .as_deref()
+            .is_none_or(|city| city.eq_ignore_ascii_case(event.city()));
+
+        city_matches
+            && match event {
+                LiveEvent::Weather(_) => true,
+                LiveEvent::Alert(event) => self.user_id == Some(event.alert.user_id),
+            }
+    }
+}
+
+/// Turns a subscription into a `text/event-stream` body. Subscribers that fall too far
+/// behind get a `lagged` event with the number of events they missed.
+pub fn sse_stream(
+    receiver: broadcast::Receiver<LiveEvent>,
+    filter: EventFilter,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let opening = Bytes::from_static(b": connected\n\n");
+
+    stream::once(async move { Ok(opening) }).chain(stream::unfold(
+        (receiver, filter),
+        |(mut receiver, filter)| async move {
+            loop {
+                let frame = match tokio::time::timeout(KEEP_ALIVE, receiver.recv()).await {
+                    Err(_) => ": keep-alive\n\n".to_string(),
+                    Ok(Ok(event)) if filter.matches(&event) => event.to_sse(),
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        format!("event: lagged\ndata: {{\"skipped\":{}}}\n\n", skipped)
+                    }
+                    Ok(Err(RecvError::Closed)) => return None,
+                };
+                return Some((Ok(Bytes::from(frame)), (receiver, filter)));
+            }
+        },
+    ))
+}
@@ -0,0 +1,323 @@
+use crate::db::Database;
+use crate::error::AppError;
+use crate::models::{AlertExportRow, ExportFilter, WeatherData};
+use actix_web::web::Bytes;
+use arrow_array::{ArrayRef, Float64Array, Int32Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use futures::channel::mpsc;
+use futures::stream::BoxStream;
+use futures::{SinkExt, StreamExt};
+use log::{error, info};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+// Rows encoded (and for Parquet, written as one row group) at a time
+const CHUNK_ROWS: usize = 5000;
+// Encoded chunks buffered ahead of a slow reader
+const CHANNEL_CHUNKS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportDataset {
+    Weather,
+    Alerts,
+}
+
+impl ExportDataset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportDataset::Weather => "weather",
+            ExportDataset::Alerts => "alerts",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Starts exporting `dataset` in the background. The encoded file arrives as a stream of
+/// chunks; rows are read from the database as they are encoded, so memory use doesn't
+/// grow with the size of the export. Dropping the receiver cancels the export.
+pub fn spawn(
+    db: Database,
+    dataset: ExportDataset,
+    format: ExportFormat,
+    filter: ExportFilter,
+) -> mpsc::Receiver<Result<Bytes, AppError>> {
+    let (tx, rx) = mpsc::channel(CHANNEL_CHUNKS);
+
+    tokio::spawn(async move {
+        let result = match dataset {
+            ExportDataset::Weather => {
+                write_rows(db.stream_weather_data(&filter), format, tx.clone()).await
+            }
+            ExportDataset::Alerts => {
+                write_rows(db.stream_alert_logs(&filter), format, tx.clone()).await
+            }
+        };
+
+        match result {
+            Ok(Some(rows)) => info!("📦 Exported {} {} row(s) as {:?}", rows, dataset.as_str(), format),
+            Ok(None) => info!("📦 Export of {} cancelled by the reader", dataset.as_str()),
+            Err(e) => {
+                error!("❌ Export of {} failed: {}", dataset.as_str(), e);
+                // The reader sees the error instead of a silently truncated file
+                let _ = tx.clone().send(Err(e)).await;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Encodes `rows` chunk by chunk into `tx`. Returns the number of rows written, or `None`
+/// if the receiver went away.
+async fn write_rows<T: ExportRow>(
+    mut rows: BoxStream<'_, Result<T, AppError>>,
+    format: ExportFormat,
+    mut tx: mpsc::Sender<Result<Bytes, AppError>>,
+) -> Result<Option<u64>, AppError> {
+    let mut encoder = Encoder::new::<T>(format)?;
+    let mut chunk = Vec::with_capacity(CHUNK_ROWS);
+    let mut total = 0;
+
+    loop {
+        let row = rows.next().await.transpose()?;
+        let done = row.is_none();
+        chunk.extend(row);
+
+        if chunk.len() == CHUNK_ROWS || (done && !chunk.is_empty()) {
+            total += chunk.len() as u64;
+            let bytes = encoder.encode(&chunk)?;
+            chunk.clear();
+            if tx.send(Ok(bytes.into())).await.is_err() {
+                return Ok(None);
+            }
+        }
+        if done {
+            break;
+        }
+    }
+
+    let trailer = encoder.finish()?;
+    if !trailer.is_empty() && tx.send(Ok(trailer.into())).await.is_err() {
+        return Ok(None);
+    }
+
+    Ok(Some(total))
+}
+
+/// A row that can be exported: CSV and NDJSON use its `Serialize` impl, Parquet its
+/// Arrow schema.
+trait ExportRow: Serialize + Send {
+    fn schema() -> SchemaRef;
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, AppError>
+    where
+        Self: Sized;
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+}
+
+impl ExportRow for WeatherData {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("city", DataType::Utf8, false),
+            Field::new("country", DataType::Utf8, false),
+            Field::new("temperature", DataType::Float64, false),
+            Field::new("feels_like", DataType::Float64, false),
+            Field::new("conditions", DataType::Utf8, false),
+            Field::new("description", DataType::Utf8, false),
+            Field::new("humidity", DataType::Int32, false),
+            Field::new("wind_speed", DataType::Float64, false),
+            Field::new("pressure", DataType::Int32, false),
+            Field::new("fetched_at", timestamp_type(), false),
+        ]))
+    }
+
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, AppError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|w| w.id.to_string()))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|w| &w.city))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|w| &w.country))),
+            Arc::new(Float64Array::from_iter_values(rows.iter().map(|w| w.temperature))),
+            Arc::new(Float64Array::from_iter_values(rows.iter().map(|w| w.feels_like))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|w| &w.conditions))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|w| &w.description))),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|w| w.humidity))),
+            Arc::new(Float64Array::from_iter_values(rows.iter().map(|w| w.wind_speed))),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|w| w.pressure))),
+            Arc::new(
+                TimestampMicrosecondArray::from_iter_values(
+                    rows.iter().map(|w| w.fetched_at.timestamp_micros()),
+                )
+                .with_timezone("UTC"),
+            ),
+        ];
+        Ok(RecordBatch::try_new(Self::schema(), columns)?)
+    }
+}
+
+impl ExportRow for AlertExportRow {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("user_id", DataType::Utf8, false),
+            Field::new("city", DataType::Utf8, false),
+            Field::new("country", DataType::Utf8, false),
+            Field::new("alert_type", DataType::Utf8, false),
+            Field::new("message", DataType::Utf8, false),
+            Field::new("channel", DataType::Utf8, false),
+            Field::new("status", DataType::Utf8, false),
+            Field::new("attempts", DataType::Int32, false),
+            Field::new("sent_at", timestamp_type(), false),
+        ]))
+    }
+
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, AppError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| a.id.to_string()))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| a.user_id.to_string()))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| &a.city))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| &a.country))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| &a.alert_type))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| &a.message))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| &a.channel))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|a| &a.status))),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|a| a.attempts))),
+            Arc::new(
+                TimestampMicrosecondArray::from_iter_values(
+                    rows.iter().map(|a| a.sent_at.timestamp_micros()),
+                )
+                .with_timezone("UTC"),
+            ),
+        ];
+        Ok(RecordBatch::try_new(Self::schema(), columns)?)
+    }
+}
+
+/// In-memory sink the CSV and Parquet writers append to; each chunk drains what was
+/// written so far
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Csv {
+        writer: csv::Writer<SharedBuffer>,
+        buffer: SharedBuffer,
+    },
+    Ndjson,
+    Parquet {
+        writer: ArrowWriter<SharedBuffer>,
+        buffer: SharedBuffer,
+    },
+}
+
+impl Encoder {
+    fn new<T: ExportRow>(format: ExportFormat) -> Result<Self, AppError> {
+        Ok(match format {
+            ExportFormat::Csv => {
+                let buffer = SharedBuffer::default();
+                let writer = csv::Writer::from_writer(buffer.clone());
+                Encoder::Csv { writer, buffer }
+            }
+            ExportFormat::Ndjson => Encoder::Ndjson,
+            ExportFormat::Parquet => {
+                let buffer = SharedBuffer::default();
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                let writer = ArrowWriter::try_new(buffer.clone(), T::schema(), Some(props))?;
+                Encoder::Parquet { writer, buffer }
+            }
+        })
+    }
+
+    fn encode<T: ExportRow>(&mut self, rows: &[T]) -> Result<Vec<u8>, AppError> {
+        match self {
+            Encoder::Csv { writer, buffer } => {
+                for row in rows {
+                    writer.serialize(row)?;
+                }
+                writer.flush()?;
+                Ok(buffer.take())
+            }
+            Encoder::Ndjson => {
+                let mut out = Vec::new();
+                for row in rows {
+                    serde_json::to_writer(&mut out, row)
+                        .map_err(|e| AppError::Export(format!("JSON: {}", e)))?;
+                    out.push(b'\n');
+                }
+                Ok(out)
+            }
+            Encoder::Parquet { writer, buffer } => {
+                writer.write(&T::to_batch(rows)?)?;
+                // Close the row group so its bytes can be sent now
+                writer.flush()?;
+                Ok(buffer.take())
+            }
+        }
+    }
+
+    /// Whatever has to follow the last row, e.g. the Parquet footer
+    fn finish(self) -> Result<Vec<u8>, AppError> {
+        match self {
+            Encoder::Csv { .. } | Encoder::Ndjson => Ok(Vec::new()),
+            Encoder::Parquet { writer, buffer } => {
+                writer.close()?;
+                Ok(buffer.take())
+            }
+        }
+    }
+}
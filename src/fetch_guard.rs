@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Lets only one weather fetch run at a time in the server process, whether started by the
+/// CRON job or the API. Triggers that arrive while a fetch runs join it instead of spending
+/// weather API quota on a second one.
+#[derive(Clone, Default)]
+pub struct FetchGuard {
+    // `None` while idle; `Some(None)` while a fetch is starting and has no run yet
+    running: Arc<Mutex<Option<Option<Uuid>>>>,
+}
+
+pub enum Claim {
+    /// Nothing was running; the permit must be held for the whole fetch
+    Started(FetchPermit),
+    /// A fetch is already running, as the given job run once it has one
+    Running(Option<Uuid>),
+}
+
+impl FetchGuard {
+    pub fn claim(&self) -> Claim {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        match *running {
+            Some(run_id) => Claim::Running(run_id),
+            None => {
+                *running = Some(None);
+                Claim::Started(FetchPermit {
+                    running: self.running.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Held by the running fetch; dropping it lets the next one start
+pub struct FetchPermit {
+    running: Arc<Mutex<Option<Option<Uuid>>>>,
+}
+
+impl FetchPermit {
+    /// Records the job run of the fetch, so joining triggers can point to it
+    pub fn set_run(&self, run_id: Uuid) {
+        *self.running.lock().unwrap_or_else(|e| e.into_inner()) = Some(Some(run_id));
+    }
+}
+
+impl Drop for FetchPermit {
+    fn drop(&mut self) {
+        *self.running.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_claim_joins_the_running_fetch() {
+        let guard = FetchGuard::default();
+
+        let Claim::Started(permit) = guard.claim() else {
+            panic!("first claim should start a fetch");
+        };
+        assert!(matches!(guard.claim(), Claim::Running(None)));
+
+        let run_id = Uuid::new_v4();
+        permit.set_run(run_id);
+        assert!(matches!(guard.claim(), Claim::Running(Some(id)) if id == run_id));
+    }
+
+    #[test]
+    fn dropping_the_permit_frees_the_guard() {
+        let guard = FetchGuard::default();
+
+        let permit = match guard.claim() {
+            Claim::Started(permit) => permit,
+            Claim::Running(_) => panic!("first claim should start a fetch"),
+        };
+        permit.set_run(Uuid::new_v4());
+        drop(permit);
+
+        assert!(matches!(guard.claim(), Claim::Started(_)));
+    }
+
+    #[test]
+    fn clones_share_the_guard() {
+        let guard = FetchGuard::default();
+        let _permit = guard.claim();
+
+        assert!(matches!(guard.clone().claim(), Claim::Running(_)));
+    }
+}
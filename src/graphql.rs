@@ -0,0 +1,291 @@
+use crate::auth::{AdminUser, AuthenticatedUser};
+use crate::db::Database;
+use crate::error::AppError;
+use crate::models::{AlertLog, UpdatePreferencesRequest, User, UserFilter, UserPreferences, WeatherData};
+use crate::pagination::{self, Cursor};
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{FromRequest, HttpRequest};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{ComplexObject, Context, EmptySubscription, Object, Schema, SimpleObject, ID};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+// Nesting and cost limits, so a single query can't ask for the whole database
+const MAX_DEPTH: usize = 10;
+const MAX_COMPLEXITY: usize = 1000;
+// Alerts per user returned by `User.alerts` when no `first` is given, and at most
+const DEFAULT_ALERTS: i64 = 20;
+const MAX_ALERTS: i64 = 100;
+
+pub type ApiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+pub fn build_schema(db: Database) -> ApiSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(db)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Runs `request` for `viewer`. Data loaders are created per request, so batched loads
+/// never serve one request's results to another.
+pub async fn execute(
+    schema: &ApiSchema,
+    db: &Database,
+    viewer: Viewer,
+    request: async_graphql::Request,
+) -> async_graphql::Response {
+    let request = request
+        .data(viewer)
+        .data(DataLoader::new(PreferencesLoader(db.clone()), tokio::spawn))
+        .data(DataLoader::new(LatestWeatherLoader(db.clone()), tokio::spawn))
+        .data(DataLoader::new(RecentAlertsLoader(db.clone()), tokio::spawn));
+
+    schema.execute(request).await
+}
+
+/// Who is making a GraphQL request. Unlike the REST extractors this doesn't reject anonymous
+/// requests, since some fields are public; credentials that are sent must be valid though.
+#[derive(Debug, Clone, Default)]
+pub struct Viewer {
+    pub user_id: Option<Uuid>,
+    pub admin: Option<AdminUser>,
+}
+
+impl Viewer {
+    fn user_id(&self) -> Result<Uuid, AppError> {
+        self.user_id
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))
+    }
+
+    fn admin(&self) -> Result<&AdminUser, AppError> {
+        match (&self.admin, self.user_id) {
+            (Some(admin), _) => Ok(admin),
+            (None, Some(_)) => Err(AppError::Forbidden("Admin role required".to_string())),
+            (None, None) => Err(AppError::Unauthorized("Admin credentials required".to_string())),
+        }
+    }
+
+    /// Users can read their own account, admins any account
+    fn ensure_can_read(&self, user_id: Uuid) -> Result<(), AppError> {
+        if self.admin.is_some() || self.user_id == Some(user_id) {
+            return Ok(());
+        }
+        self.user_id()?;
+        Err(AppError::Forbidden(
+            "You can only access your own account".to_string(),
+        ))
+    }
+}
+
+impl FromRequest for Viewer {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            let has_token = req.headers().contains_key(AUTHORIZATION);
+            let has_admin_key = req.headers().contains_key("X-Admin-Key");
+
+            let user_id = if has_token {
+                Some(AuthenticatedUser::extract(&req).await?.user_id)
+            } else {
+                None
+            };
+            let admin = if has_token || has_admin_key {
+                match AdminUser::extract(&req).await {
+                    Ok(admin) => Some(admin),
+                    // A signed-in user without the admin role
+                    Err(AppError::Forbidden(_)) => None,
+                    Err(e) => return Err(e),
+                }
+            } else {
+                None
+            };
+
+            Ok(Viewer { user_id, admin })
+        })
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The signed-in user
+    async fn me(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        let user_id = ctx.data::<Viewer>()?.user_id()?;
+        let user = ctx
+            .data::<Database>()?
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+        Ok(user)
+    }
+
+    /// A user by ID: your own account, or any account for admins
+    async fn user(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Option<User>> {
+        let user_id = parse_id(&id)?;
+        ctx.data::<Viewer>()?.ensure_can_read(user_id)?;
+
+        Ok(ctx.data::<Database>()?.get_user_by_id(user_id).await?)
+    }
+
+    /// Users, newest first (admin only). Pass `nextCursor` as `after` for the next page.
+    async fn users(
+        &self,
+        ctx: &Context<'_>,
+        city: Option<String>,
+        country: Option<String>,
+        #[graphql(default = 50)] first: i64,
+        after: Option<String>,
+    ) -> async_graphql::Result<UserPage> {
+        let db = ctx.data::<Database>()?;
+        ctx.data::<Viewer>()?
+            .admin()?
+            .audit(db, "graphql.users", None)
+            .await?;
+
+        let filter = UserFilter { city, country };
+        let after = Cursor::parse(after.as_deref())?;
+        let limit = pagination::page_size(Some(first), 50);
+        let page = db.get_all_users(&filter, after, limit).await?;
+
+        Ok(UserPage {
+            items: page.items,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    /// Latest reading for a city (case-insensitive)
+    async fn weather(&self, ctx: &Context<'_>, city: String) -> async_graphql::Result<Option<WeatherData>> {
+        let loader = ctx.data::<DataLoader<LatestWeatherLoader>>()?;
+        Ok(loader.load_one(city.to_lowercase()).await?)
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Changes the signed-in user's alert preferences; fields left out are unchanged
+    async fn update_preferences(
+        &self,
+        ctx: &Context<'_>,
+        input: UpdatePreferencesRequest,
+    ) -> async_graphql::Result<UserPreferences> {
+        let user_id = ctx.data::<Viewer>()?.user_id()?;
+        input
+            .validate()
+            .map_err(|e| AppError::Validation(e.to_string()))?;
+
+        Ok(ctx
+            .data::<Database>()?
+            .update_user_preferences(user_id, &input)
+            .await?)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct UserPage {
+    pub items: Vec<User>,
+    pub next_cursor: Option<String>,
+}
+
+#[ComplexObject]
+impl User {
+    async fn preferences(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<UserPreferences>> {
+        let loader = ctx.data::<DataLoader<PreferencesLoader>>()?;
+        Ok(loader.load_one(self.id).await?)
+    }
+
+    /// Latest reading for the user's city
+    async fn latest_weather(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<WeatherData>> {
+        let loader = ctx.data::<DataLoader<LatestWeatherLoader>>()?;
+        Ok(loader.load_one(self.city.to_lowercase()).await?)
+    }
+
+    /// The user's most recent alerts, newest first
+    async fn alerts(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] first: i64,
+    ) -> async_graphql::Result<Vec<AlertLog>> {
+        let loader = ctx.data::<DataLoader<RecentAlertsLoader>>()?;
+        let first = if first < 1 { DEFAULT_ALERTS } else { first.min(MAX_ALERTS) };
+        let alerts = loader.load_one((self.id, first)).await?;
+
+        Ok(alerts.unwrap_or_default())
+    }
+}
+
+fn parse_id(id: &ID) -> Result<Uuid, AppError> {
+    Uuid::parse_str(id).map_err(|_| AppError::Validation("Invalid ID".to_string()))
+}
+
+/// Preferences by user ID
+pub struct PreferencesLoader(Database);
+
+impl Loader<Uuid> for PreferencesLoader {
+    type Value = UserPreferences;
+    type Error = Arc<AppError>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let prefs = self.0.get_preferences_for_users(keys).await?;
+        Ok(prefs.into_iter().map(|p| (p.user_id, p)).collect())
+    }
+}
+
+/// Latest reading by lowercase city name
+pub struct LatestWeatherLoader(Database);
+
+impl Loader<String> for LatestWeatherLoader {
+    type Value = WeatherData;
+    type Error = Arc<AppError>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let weather = self.0.get_latest_weather_for_cities(keys).await?;
+        Ok(weather
+            .into_iter()
+            .map(|w| (w.city.to_lowercase(), w))
+            .collect())
+    }
+}
+
+/// The most recent alerts by `(user ID, count)`; one query per distinct count
+pub struct RecentAlertsLoader(Database);
+
+impl Loader<(Uuid, i64)> for RecentAlertsLoader {
+    type Value = Vec<AlertLog>;
+    type Error = Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[(Uuid, i64)],
+    ) -> Result<HashMap<(Uuid, i64), Self::Value>, Self::Error> {
+        let mut by_count: HashMap<i64, Vec<Uuid>> = HashMap::new();
+        for (user_id, count) in keys {
+            by_count.entry(*count).or_default().push(*user_id);
+        }
+
+        let mut loaded = HashMap::new();
+        for (count, user_ids) in by_count {
+            for alert in self.0.get_recent_alerts_for_users(&user_ids, count).await? {
+                loaded
+                    .entry((alert.user_id, count))
+                    .or_insert_with(Vec::new)
+                    .push(alert);
+            }
+        }
+
+        Ok(loaded)
+    }
+}
@@ -1,38 +1,253 @@
-use crate::error::AppError;
+use crate::auth::{self, AdminUser, AuthenticatedUser};
+use crate::error::{AppError, ErrorBody};
+use crate::events::{self, AlertEvent, EventFilter};
+use crate::export::{self, ExportDataset, ExportFormat};
+use crate::fetch_guard::Claim;
+use crate::graphql::{self as gql, Viewer};
+use crate::idempotency::{Idempotency, IDEMPOTENCY_KEY};
 use crate::models::*;
+use crate::pagination::{self, Cursor};
+use crate::preview::{self, NotificationPreview, PreviewFormat, PreviewKind};
+use crate::rate_limit::{RateLimit, RouteGroup};
+use crate::tokens::{self, TokenPurpose};
 use crate::AppState;
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::http::header::{self, ContentEncoding};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
 use log::info;
+use utoipa::openapi::path::{ParameterBuilder, ParameterIn};
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::Required;
+use utoipa::{IntoParams, Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
 use validator::Validate;
 
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    // Registered before the `/api` scope, which would otherwise claim /api/openapi.json
+    cfg.service(SwaggerUi::new("/docs/{_:.*}").url("/api/openapi.json", ApiDoc::openapi()))
+        .service(web::redirect("/docs", "/docs/"));
+
     cfg.service(
         web::scope("/api")
+            .wrap(RateLimit::new(RouteGroup::Api))
             .route("/health", web::get().to(health_check))
             .service(
                 web::scope("/users")
-                    .route("", web::post().to(create_user))
+                    .route(
+                        "",
+                        web::post()
+                            .to(create_user)
+                            .wrap(Idempotency)
+                            .wrap(RateLimit::new(RouteGroup::Signup)),
+                    )
                     .route("", web::get().to(get_all_users))
+                    .route("/verify", web::get().to(verify_email_page))
+                    .route("/verify", web::post().to(verify_email))
                     .route("/{user_id}", web::get().to(get_user))
+                    .route("/{user_id}", web::patch().to(update_user))
+                    .route("/{user_id}", web::delete().to(delete_user))
+                    .route("/{user_id}/export", web::get().to(export_user_data))
                     .route("/{user_id}/preferences", web::get().to(get_preferences))
-                    .route("/{user_id}/preferences", web::put().to(update_preferences))
+                    .route(
+                        "/{user_id}/preferences",
+                        web::put().to(update_preferences).wrap(Idempotency),
+                    )
                     .route("/{user_id}/alerts", web::get().to(get_user_alerts)),
             )
+            .service(
+                web::scope("/auth")
+                    .route(
+                        "/login",
+                        web::post()
+                            .to(request_login_link)
+                            .wrap(RateLimit::new(RouteGroup::Signup)),
+                    )
+                    .route("/verify", web::get().to(login_page))
+                    .route("/verify", web::post().to(complete_login))
+                    .route("/logout", web::post().to(logout)),
+            )
             .service(
                 web::scope("/weather")
                     .route("/current/{city}", web::get().to(get_current_weather))
                     .route("/history/{city}", web::get().to(get_weather_history))
-                    .route("/fetch", web::post().to(manual_fetch_weather)),
+                    .route(
+                        "/fetch",
+                        web::post()
+                            .to(manual_fetch_weather)
+                            .wrap(RateLimit::new(RouteGroup::Fetch)),
+                    ),
+            )
+            .route("/events", web::get().to(stream_events))
+            .route("/events/token", web::post().to(issue_stream_token))
+            .route("/graphql", web::post().to(graphql))
+            .route("/graphql", web::get().to(graphiql))
+            .service(
+                web::scope("/jobs")
+                    .route("", web::get().to(get_job_runs))
+                    .route("/{job_id}", web::get().to(get_job_run)),
             )
             .service(
                 web::scope("/alerts")
                     .route("", web::get().to(get_all_alerts)),
+            )
+            .service(
+                web::scope("/subscription")
+                    .route("", web::get().to(get_subscription))
+                    .route("", web::delete().to(delete_subscription))
+                    .route(
+                        "/preferences",
+                        web::put()
+                            .to(update_subscription_preferences)
+                            .wrap(Idempotency),
+                    )
+                    .route("/pause", web::post().to(pause_subscription))
+                    .route("/resume", web::post().to(resume_subscription))
+                    .route("/unsubscribe", web::get().to(unsubscribe_page))
+                    .route("/unsubscribe", web::post().to(one_click_unsubscribe)),
+            )
+            .service(
+                web::scope("/admin")
+                    .route("/outbox", web::get().to(get_outbox_messages))
+                    .route("/outbox/{message_id}/requeue", web::post().to(requeue_outbox_message))
+                    .route("/suppressions", web::get().to(get_suppressions))
+                    .route("/suppressions/{email}", web::delete().to(remove_suppression))
+                    .route("/preview/{user_id}", web::get().to(preview_notification))
+                    .route("/audit", web::get().to(get_admin_audit_log))
+                    .route("/export/{dataset}", web::get().to(export_data)),
+            )
+            .service(
+                web::scope("/webhooks")
+                    .route("/mail", web::post().to(mail_webhook)),
             ),
     );
 }
 
+/// OpenAPI document of the routes above, served at `/api/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Weather Alert System API",
+        description = "Weather monitoring with email alerts. Every JSON response is wrapped in `ApiResponse`."
+    ),
+    paths(
+        health_check,
+        create_user,
+        verify_email_page,
+        verify_email,
+        get_all_users,
+        get_user,
+        update_user,
+        delete_user,
+        export_user_data,
+        get_preferences,
+        update_preferences,
+        get_user_alerts,
+        request_login_link,
+        login_page,
+        complete_login,
+        logout,
+        get_current_weather,
+        get_weather_history,
+        manual_fetch_weather,
+        get_job_runs,
+        get_job_run,
+        stream_events,
+        issue_stream_token,
+        graphql,
+        graphiql,
+        get_all_alerts,
+        get_subscription,
+        update_subscription_preferences,
+        pause_subscription,
+        resume_subscription,
+        delete_subscription,
+        unsubscribe_page,
+        one_click_unsubscribe,
+        get_outbox_messages,
+        requeue_outbox_message,
+        get_suppressions,
+        remove_suppression,
+        preview_notification,
+        get_admin_audit_log,
+        export_data,
+        mail_webhook,
+    ),
+    components(schemas(WeatherBucket, AlertEvent)),
+    modifiers(&SecuritySchemes, &IdempotencyKeyHeader)
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        components.add_security_scheme(
+            "admin_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Admin-Key"))),
+        );
+        components.add_security_scheme(
+            "webhook_secret",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Webhook-Secret"))),
+        );
+    }
+}
+
+/// Routes wrapped in `Idempotency` in `configure_routes`
+const IDEMPOTENT_ROUTES: [&str; 3] = [
+    "/api/users",
+    "/api/users/{user_id}/preferences",
+    "/api/subscription/preferences",
+];
+
+/// Documents the optional `Idempotency-Key` header on the `POST` and `PUT` operations of
+/// `IDEMPOTENT_ROUTES`
+struct IdempotencyKeyHeader;
+
+impl Modify for IdempotencyKeyHeader {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let header = ParameterBuilder::new()
+            .name(IDEMPOTENCY_KEY)
+            .parameter_in(ParameterIn::Header)
+            .required(Required::False)
+            .description(Some(
+                "Makes the request safe to retry: repeats with the same key and body within \
+                 the key's lifetime replay the first response (with `Idempotent-Replayed: true`), \
+                 the same key with a different body gets 422",
+            ))
+            .schema(Some(ObjectBuilder::new().schema_type(Type::String).max_length(Some(255))))
+            .build();
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if !IDEMPOTENT_ROUTES.contains(&path.as_str()) {
+                continue;
+            }
+            for operation in [item.post.as_mut(), item.put.as_mut()].into_iter().flatten() {
+                operation
+                    .parameters
+                    .get_or_insert_with(Vec::new)
+                    .push(header.clone());
+            }
+        }
+    }
+}
+
 // Health check endpoint
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "health",
+    summary = "Service health",
+    responses(
+        (status = 200, description = "Service is up", body = Object),
+    ),
+)]
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({
         "status": "healthy",
@@ -42,13 +257,30 @@ async fn health_check() -> impl Responder {
 }
 
 // User endpoints
+#[utoipa::path(
+    post,
+    path = "/api/users",
+    tag = "users",
+    summary = "Register a user",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 201, description = "User registered, pending email confirmation", body = ApiResponse<User>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 409, description = "Conflict", body = ErrorBody),
+        (status = 429, description = "Too many sign-ups; see `Retry-After`", body = ErrorBody),
+    ),
+)]
 async fn create_user(
     state: web::Data<AppState>,
     req: web::Json<CreateUserRequest>,
 ) -> Result<HttpResponse, AppError> {
+    let mut req = req.into_inner();
+    req.email = normalize_email(&req.email);
     req.validate()
         .map_err(|e| AppError::Validation(e.to_string()))?;
 
+    req.locale = req.locale.as_deref().map(supported_locale).transpose()?;
+
     // Check if user already exists
     if let Some(_existing) = state.db.get_user_by_email(&req.email).await? {
         return Err(AppError::Conflict("User with this email already exists".to_string()));
@@ -56,33 +288,180 @@ async fn create_user(
 
     let user = state.db.create_user(&req).await?;
 
-    // Send welcome email
-    tokio::spawn({
-        let email_client = state.email_client.clone();
-        let user_email = user.email.clone();
-        let city = user.city.clone();
-        async move {
-            if let Err(e) = email_client.send_welcome_email(&user_email, &city).await {
-                log::error!("Failed to send welcome email: {}", e);
-            }
-        }
-    });
+    // The account stays pending, and receives no alerts, until the address is confirmed
+    let verification = Notification::Verification {
+        city: user.city.clone(),
+        locale: user.locale.clone(),
+    };
+    crate::outbox::enqueue(&state.db, &state.config, Some(user.id), None, &user.email, &verification)
+        .await?;
 
     Ok(HttpResponse::Created().json(ApiResponse::success(
         user,
-        "User registered successfully. Welcome email sent!",
+        "User registered. Check your inbox to confirm your email address.",
     )))
 }
 
-async fn get_all_users(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
-    let users = state.db.get_all_users().await?;
-    Ok(HttpResponse::Ok().json(ApiResponse::success(users, "Users fetched successfully")))
+/// Addresses are stored trimmed and in lowercase, so `Foo@Example.com ` can't register
+/// twice
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn supported_locale(locale: &str) -> Result<String, AppError> {
+    crate::i18n::normalize(locale)
+        .map(|supported| supported.to_string())
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "Unsupported locale '{}' (supported: {})",
+                locale,
+                crate::i18n::supported_locales().join(", ")
+            ))
+        })
 }
 
+// Like the unsubscribe link, the verification link only shows a form on GET so that
+// mail scanners following links cannot confirm an address on the user's behalf
+#[utoipa::path(
+    get,
+    path = "/api/users/verify",
+    tag = "users",
+    summary = "Email confirmation page",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "HTML page with a confirmation form", content_type = "text/html", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+)]
+async fn verify_email_page(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    state.tokens.verify_for_address(&query.token, TokenPurpose::Verify)?;
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html>
+<body style="font-family: Arial, sans-serif; padding: 20px;">
+    <h2>Confirm your weather alert subscription</h2>
+    <p>Click below to confirm your email address and start receiving weather alerts.</p>
+    <form method="post" action="/api/users/verify?token={}">
+        <button type="submit">Confirm subscription</button>
+    </form>
+</body>
+</html>"#,
+        query.token
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(body))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/verify",
+    tag = "users",
+    summary = "Confirm an email address",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "User activated", body = ApiResponse<User>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+)]
+async fn verify_email(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (user_id, email) = state
+        .tokens
+        .verify_for_address(&query.token, TokenPurpose::Verify)?;
+
+    let Some(user) = state.db.activate_pending_user(user_id, &email).await? else {
+        let user = state
+            .db
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+        // A link sent to an earlier address must not confirm the current one
+        if user.email != email {
+            return Err(AppError::Unauthorized(
+                "This link was sent to a previous email address".to_string(),
+            ));
+        }
+        // Already confirmed (or since unsubscribed); nothing to change
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(user, "Email already verified")));
+    };
+
+    info!("✅ Email verified: {}", user.email);
+
+    let welcome = Notification::Welcome {
+        city: user.city.clone(),
+        locale: user.locale.clone(),
+    };
+    crate::outbox::enqueue(&state.db, &state.config, Some(user.id), None, &user.email, &welcome)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        user,
+        "Email verified. Welcome email sent!",
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users",
+    tag = "users",
+    summary = "List users",
+    params(UserQuery),
+    responses(
+        (status = 200, description = "One page of users, newest first", body = ApiResponse<Vec<User>>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn get_all_users(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    query: web::Query<UserQuery>,
+) -> Result<HttpResponse, AppError> {
+    admin.audit(&state.db, "users.list", None).await?;
+
+    let filter = UserFilter {
+        city: query.city.clone(),
+        country: query.country.clone(),
+    };
+    let after = Cursor::parse(query.cursor.as_deref())?;
+    let limit = pagination::page_size(query.limit, 100);
+
+    let users = state.db.get_all_users(&filter, after, limit).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::page(users, "Users fetched successfully")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}",
+    tag = "users",
+    summary = "Get a user with preferences",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "User and preferences", body = ApiResponse<UserWithPreferences>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
 async fn get_user(
     state: web::Data<AppState>,
+    auth: AuthenticatedUser,
     user_id: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
+    auth.ensure_owns(*user_id)?;
+
     let user = state
         .db
         .get_user_by_id(*user_id)
@@ -96,11 +475,320 @@ async fn get_user(
     Ok(HttpResponse::Ok().json(ApiResponse::success(response, "User fetched successfully")))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/api/users/{user_id}",
+    tag = "users",
+    summary = "Update email, location or language",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "Updated user", body = ApiResponse<User>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflict", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
+async fn update_user(
+    state: web::Data<AppState>,
+    auth: AuthenticatedUser,
+    user_id: web::Path<Uuid>,
+    req: web::Json<UpdateUserRequest>,
+) -> Result<HttpResponse, AppError> {
+    auth.ensure_owns(*user_id)?;
+    let mut req = req.into_inner();
+    req.email = req.email.as_deref().map(normalize_email);
+    req.validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    req.locale = req.locale.as_deref().map(supported_locale).transpose()?;
+
+    let current = state
+        .db
+        .get_user_by_id(*user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let email_changed = req.email.as_deref().is_some_and(|email| email != current.email);
+
+    if let (Some(email), true) = (&req.email, email_changed) {
+        if let Some(existing) = state.db.get_user_by_email(email).await? {
+            if existing.id != *user_id {
+                return Err(AppError::Conflict("User with this email already exists".to_string()));
+            }
+        }
+    }
+
+    // A new address must be confirmed like a new registration; until then the user is
+    // pending and gets no alerts
+    let user = state
+        .db
+        .update_user(*user_id, &req, email_changed)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    if email_changed {
+        let verification = Notification::Verification {
+            city: user.city.clone(),
+            locale: user.locale.clone(),
+        };
+        crate::outbox::enqueue(&state.db, &state.config, Some(user.id), None, &user.email, &verification)
+            .await?;
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(
+            user,
+            "User updated. Check your inbox to confirm your new email address.",
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(user, "User updated successfully")))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/{user_id}",
+    tag = "users",
+    summary = "Delete a user and all their data",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "User deleted", body = ApiResponse<serde_json::Value>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
+async fn delete_user(
+    state: web::Data<AppState>,
+    auth: AuthenticatedUser,
+    user_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    auth.ensure_owns(*user_id)?;
+
+    if !state.db.delete_user(*user_id).await? {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success((), "User deleted")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/export",
+    tag = "users",
+    summary = "Export all data stored about a user",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "User, preferences, alert history, tokens and suppression", body = ApiResponse<UserDataExport>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
+async fn export_user_data(
+    state: web::Data<AppState>,
+    auth: AuthenticatedUser,
+    user_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    auth.ensure_owns(*user_id)?;
+
+    let user = state
+        .db
+        .get_user_by_id(*user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let export = UserDataExport {
+        exported_at: Utc::now(),
+        preferences: state.db.get_user_preferences(user.id).await?,
+        alerts: state.db.get_user_alert_history(user.id).await?,
+        api_tokens: state.db.get_user_api_tokens(user.id).await?,
+        suppression: state.db.get_suppression(&user.email).await?,
+        user,
+    };
+
+    let filename = format!("weather-alerts-{}.json", export.user.id);
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .json(ApiResponse::success(export, "User data exported")))
+}
+
+// Auth endpoints
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    summary = "Email a sign-in link",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Same answer whether or not the address is registered", body = ApiResponse<serde_json::Value>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 429, description = "Too many login requests; see `Retry-After`", body = ErrorBody),
+    ),
+)]
+async fn request_login_link(
+    state: web::Data<AppState>,
+    req: web::Json<LoginRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut req = req.into_inner();
+    req.email = normalize_email(&req.email);
+    req.validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    // Unverified registrations must confirm their address first
+    if let Some(user) = state.db.get_user_by_email(&req.email).await? {
+        if user.status != "pending" {
+            let notification = Notification::LoginLink {
+                locale: user.locale.clone(),
+            };
+            crate::outbox::enqueue(&state.db, &state.config, Some(user.id), None, &user.email, &notification)
+                .await?;
+        }
+    }
+
+    // Same answer whether or not the address is registered, so it can't be probed
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        (),
+        "If this address is registered, a sign-in link is on its way",
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/verify",
+    tag = "auth",
+    summary = "Sign-in page",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "HTML page with a sign-in form", content_type = "text/html", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+)]
+async fn login_page(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    state.tokens.verify(&query.token, TokenPurpose::Login)?;
+
+    // Mail scanners follow links, so the token is only exchanged on an explicit POST
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html>
+<body style="font-family: Arial, sans-serif; padding: 20px;">
+    <h2>Sign in to Weather Alert System</h2>
+    <p>Click below to sign in and receive an API token.</p>
+    <form method="post" action="/api/auth/verify?token={}">
+        <button type="submit">Sign in</button>
+    </form>
+</body>
+</html>"#,
+        query.token
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(body))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify",
+    tag = "auth",
+    summary = "Exchange a sign-in link for an API token",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "API token issued", body = ApiResponse<IssuedToken>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+)]
+async fn complete_login(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = state.tokens.verify(&query.token, TokenPurpose::Login)?;
+    let user = state
+        .db
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    // The link itself is stateless, so remember it until it expires to make it single-use
+    let link_hash = auth::hash_token(&query.token);
+    if !state
+        .db
+        .use_login_link(&link_hash, state.config.login_link_ttl_minutes)
+        .await?
+    {
+        return Err(AppError::Unauthorized(
+            "This sign-in link was already used".to_string(),
+        ));
+    }
+
+    let token = auth::generate_token();
+    let api_token = state
+        .db
+        .create_api_token(user.id, &auth::hash_token(&token), state.config.api_token_ttl_days)
+        .await?;
+
+    info!("🔑 API token issued for {}", user.email);
+
+    let issued = IssuedToken {
+        token,
+        user_id: user.id,
+        expires_at: api_token.expires_at,
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success(issued, "Signed in")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    summary = "Revoke the token used for the request",
+    responses(
+        (status = 200, description = "Token revoked", body = ApiResponse<serde_json::Value>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
+async fn logout(
+    state: web::Data<AppState>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    state.db.delete_api_token(auth.token_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success((), "Signed out")))
+}
+
 // Preferences endpoints
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/preferences",
+    tag = "users",
+    summary = "Get alert preferences",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Preferences", body = ApiResponse<UserPreferences>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
 async fn get_preferences(
     state: web::Data<AppState>,
+    auth: AuthenticatedUser,
     user_id: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
+    auth.ensure_owns(*user_id)?;
+
     let preferences = state
         .db
         .get_user_preferences(*user_id)
@@ -110,11 +798,28 @@ async fn get_preferences(
     Ok(HttpResponse::Ok().json(ApiResponse::success(preferences, "Preferences fetched")))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/users/{user_id}/preferences",
+    tag = "users",
+    summary = "Update alert preferences",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    request_body = UpdatePreferencesRequest,
+    responses(
+        (status = 200, description = "Updated preferences", body = ApiResponse<UserPreferences>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
 async fn update_preferences(
     state: web::Data<AppState>,
+    auth: AuthenticatedUser,
     user_id: web::Path<Uuid>,
     req: web::Json<UpdatePreferencesRequest>,
 ) -> Result<HttpResponse, AppError> {
+    auth.ensure_owns(*user_id)?;
     req.validate()
         .map_err(|e| AppError::Validation(e.to_string()))?;
 
@@ -127,6 +832,17 @@ async fn update_preferences(
 }
 
 // Weather endpoints
+#[utoipa::path(
+    get,
+    path = "/api/weather/current/{city}",
+    tag = "weather",
+    summary = "Latest reading for a city",
+    params(("city" = String, Path, description = "City name (case-insensitive)")),
+    responses(
+        (status = 200, description = "Latest reading", body = ApiResponse<WeatherData>),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+)]
 async fn get_current_weather(
     state: web::Data<AppState>,
     city: web::Path<String>,
@@ -140,68 +856,1033 @@ async fn get_current_weather(
     Ok(HttpResponse::Ok().json(ApiResponse::success(weather, "Weather data fetched")))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/weather/history/{city}",
+    tag = "weather",
+    summary = "Readings or aggregates over a time range",
+    params(("city" = String, Path, description = "City name (case-insensitive)"), HistoryQuery),
+    responses(
+        (status = 200, description = "Raw readings (paginated, newest first), or with `bucket` one aggregate per bucket (`ApiResponse<Vec<WeatherBucket>>`)", body = ApiResponse<Vec<WeatherData>>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+    ),
+)]
 async fn get_weather_history(
     state: web::Data<AppState>,
     city: web::Path<String>,
     query: web::Query<HistoryQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let limit = query.limit.unwrap_or(24); // Default 24 hours
-    let history = state.db.get_weather_history(&city, limit).await?;
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(AppError::Validation("`from` must be before `to`".to_string()));
+        }
+    }
+
+    // Without a range, cover the last day (raw readings, hourly buckets) or the bucket's
+    // default range ending now
+    let to = query.to.unwrap_or_else(Utc::now);
+    let range = query
+        .bucket
+        .map_or(chrono::Duration::hours(24), |bucket| bucket.default_range());
+    let from = query.from.unwrap_or(to - range);
+
+    if let Some(bucket) = query.bucket {
+        bucket.check_range(from, to)?;
+        let buckets = state.db.get_weather_buckets(&city, bucket, from, to).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(
+            buckets,
+            "Weather history aggregated",
+        )));
+    }
+
+    let limit = pagination::page_size(query.limit, 100);
+    let after = Cursor::parse(query.cursor.as_deref())?;
+    let history = state
+        .db
+        .get_weather_history(&city, Some(from), query.to, after, limit)
+        .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(history, "Weather history fetched")))
+    Ok(HttpResponse::Ok().json(ApiResponse::page(history, "Weather history fetched")))
 }
 
-async fn manual_fetch_weather(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
-    info!("🔄 Manual weather fetch triggered via API");
+#[utoipa::path(
+    post,
+    path = "/api/weather/fetch",
+    tag = "weather",
+    summary = "Fetch weather for all cities now",
+    responses(
+        (status = 202, description = "Fetch started in the background, or the fetch already running; follow it at `Location`", body = ApiResponse<JobRun>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 409, description = "A fetch is just starting", body = ErrorBody),
+        (status = 429, description = "Too many fetches; see `Retry-After`", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn manual_fetch_weather(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+) -> Result<HttpResponse, AppError> {
+    admin.audit(&state.db, "weather.fetch", None).await?;
+    info!("🔄 Manual weather fetch triggered via API by {}", admin.actor);
+
+    let permit = match state.fetch_guard.claim() {
+        Claim::Started(permit) => permit,
+        // Join the running fetch rather than spend weather API quota on another
+        Claim::Running(Some(run_id)) => {
+            let run = state
+                .db
+                .get_job_run(run_id)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Job run not found".to_string()))?;
+            return Ok(HttpResponse::Accepted()
+                .insert_header(("Location", format!("/api/jobs/{}", run.id)))
+                .json(ApiResponse::success(run, "Weather fetch already running")));
+        }
+        Claim::Running(None) => {
+            return Err(AppError::Conflict(
+                "A weather fetch is starting, try again shortly".to_string(),
+            ));
+        }
+    };
+
+    let run = state
+        .db
+        .create_job_run(JOB_WEATHER_FETCH, "api", Some(&admin.actor))
+        .await?;
+    permit.set_run(run.id);
 
     // Spawn background task
     tokio::spawn({
         let db = state.db.clone();
+        let config = state.config.clone();
         let weather_client = state.weather_client.clone();
-        let email_client = state.email_client.clone();
+        let events = state.events.clone();
+        let run_id = run.id;
 
         async move {
-            match crate::fetch_and_alert(&db, &weather_client, &email_client).await {
-                Ok(_) => info!("✅ Manual weather fetch completed"),
-                Err(e) => log::error!("❌ Manual weather fetch failed: {}", e),
+            match crate::fetch_and_alert(&db, &config, &weather_client, &events, run_id).await {
+                Ok(_) => info!("✅ Manual weather fetch {} completed", run_id),
+                Err(e) => log::error!("❌ Manual weather fetch {} failed: {}", run_id, e),
             }
+            drop(permit);
         }
     });
 
-    Ok(HttpResponse::Accepted().json(ApiResponse::<()>::error(
-        "Weather fetch started in background",
-    )))
+    Ok(HttpResponse::Accepted()
+        .insert_header(("Location", format!("/api/jobs/{}", run.id)))
+        .json(ApiResponse::success(run, "Weather fetch started in background")))
+}
+
+// Live events
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "weather",
+    summary = "Stream new observations and your alerts as they happen",
+    description = "Server-Sent Events. `weather` events carry a `WeatherData`, `alert` events an `AlertEvent` (only sent to the user the alert is for, when the request has a bearer token or stream token), `lagged` events the number of events a slow reader missed.",
+    params(EventQuery),
+    responses(
+        (status = 200, description = "Event stream", content((String = "text/event-stream"))),
+        (status = 401, description = "Invalid bearer token or stream token", body = ErrorBody),
+    ),
+    security((), ("bearer_token" = [])),
+)]
+async fn stream_events(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<EventQuery>,
+) -> Result<HttpResponse, AppError> {
+    let query = query.into_inner();
+
+    // Observations are public; a token, if given, must be valid and adds the user's alerts
+    let user_id = if let Some(token) = &query.token {
+        Some(state.tokens.verify(token, TokenPurpose::Stream)?)
+    } else if req.headers().contains_key(header::AUTHORIZATION) {
+        Some(AuthenticatedUser::extract(&req).await?.user_id)
+    } else {
+        None
+    };
+
+    let filter = EventFilter {
+        city: query.city,
+        user_id,
+    };
+    let stream = events::sse_stream(state.events.subscribe(), filter);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Keeps the compression middleware from buffering events
+        .insert_header(ContentEncoding::Identity)
+        .streaming(stream))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/events/token",
+    tag = "weather",
+    summary = "Issue a token for opening the event stream",
+    description = "Browsers' `EventSource` can't send an `Authorization` header, so pass this token as `token` to `GET /api/events` instead. It is only checked when the stream opens; fetch a new one to reconnect after it expires.",
+    responses(
+        (status = 200, description = "Stream token issued", body = ApiResponse<IssuedToken>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
+async fn issue_stream_token(
+    state: web::Data<AppState>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let ttl = chrono::Duration::minutes(state.config.stream_token_ttl_minutes);
+    let issued = IssuedToken {
+        token: state.tokens.sign(auth.user_id, TokenPurpose::Stream, ttl),
+        user_id: auth.user_id,
+        expires_at: Utc::now() + ttl,
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(issued, "Stream token issued")))
+}
+
+// GraphQL
+#[utoipa::path(
+    post,
+    path = "/api/graphql",
+    tag = "graphql",
+    summary = "Run a GraphQL query or mutation",
+    description = "Users with their preferences, the latest weather for their city and recent alerts in one round trip. Send `{\"query\": ..., \"variables\": ...}`; see the schema in GraphiQL at `GET /api/graphql`.",
+    request_body(content = Object, content_type = "application/json"),
+    responses(
+        (status = 200, description = "GraphQL response; field errors are reported in `errors`", body = Object),
+        (status = 401, description = "Invalid credentials", body = ErrorBody),
+    ),
+    security((), ("bearer_token" = []), ("admin_key" = [])),
+)]
+async fn graphql(
+    state: web::Data<AppState>,
+    viewer: Viewer,
+    request: async_graphql_actix_web::GraphQLRequest,
+) -> async_graphql_actix_web::GraphQLResponse {
+    gql::execute(&state.graphql, &state.db, viewer, request.into_inner())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/graphql",
+    tag = "graphql",
+    summary = "GraphiQL explorer for the GraphQL API",
+    responses((status = 200, description = "HTML page", content_type = "text/html")),
+)]
+async fn graphiql() -> HttpResponse {
+    let page = async_graphql::http::GraphiQLSource::build()
+        .endpoint("/api/graphql")
+        .finish();
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page)
+}
+
+// Job endpoints
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "jobs",
+    summary = "List recent job runs",
+    params(LimitQuery),
+    responses(
+        (status = 200, description = "Job runs, newest first", body = ApiResponse<Vec<JobRun>>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn get_job_runs(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    query: web::Query<LimitQuery>,
+) -> Result<HttpResponse, AppError> {
+    admin.audit(&state.db, "jobs.list", None).await?;
+
+    let limit = pagination::page_size(query.limit, 50);
+    let runs = state.db.get_job_runs(limit).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(runs, "Job runs fetched")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{job_id}",
+    tag = "jobs",
+    summary = "Get the status and progress of a job run",
+    params(("job_id" = Uuid, Path, description = "Job run ID")),
+    responses(
+        (status = 200, description = "Job run", body = ApiResponse<JobRun>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Job run not found", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn get_job_run(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    job_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let job_id = job_id.into_inner();
+    admin.audit(&state.db, "jobs.get", Some(&job_id.to_string())).await?;
+
+    let run = state
+        .db
+        .get_job_run(job_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Job run not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(run, "Job run fetched")))
 }
 
 // Alert endpoints
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/alerts",
+    tag = "alerts",
+    summary = "List a user's alerts",
+    params(("user_id" = Uuid, Path, description = "User id"), AlertQuery),
+    responses(
+        (status = 200, description = "One page of alerts, newest first", body = ApiResponse<Vec<AlertLog>>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("bearer_token" = [])),
+)]
 async fn get_user_alerts(
     state: web::Data<AppState>,
+    auth: AuthenticatedUser,
     user_id: web::Path<Uuid>,
     query: web::Query<AlertQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let limit = query.limit.unwrap_or(50);
-    let alerts = state.db.get_user_alerts(*user_id, limit).await?;
+    auth.ensure_owns(*user_id)?;
+
+    let limit = pagination::page_size(query.limit, 50);
+    let after = Cursor::parse(query.cursor.as_deref())?;
+    let alerts = state
+        .db
+        .get_user_alerts(*user_id, &query.filter()?, after, limit)
+        .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(alerts, "Alerts fetched")))
+    Ok(HttpResponse::Ok().json(ApiResponse::page(alerts, "Alerts fetched")))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/alerts",
+    tag = "alerts",
+    summary = "List all alerts",
+    params(AlertQuery),
+    responses(
+        (status = 200, description = "One page of alerts, newest first", body = ApiResponse<Vec<AlertLog>>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
 async fn get_all_alerts(
     state: web::Data<AppState>,
+    admin: AdminUser,
     query: web::Query<AlertQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let limit = query.limit.unwrap_or(100);
-    let alerts = state.db.get_all_alerts(limit).await?;
+    admin.audit(&state.db, "alerts.list", None).await?;
+
+    let limit = pagination::page_size(query.limit, 100);
+    let after = Cursor::parse(query.cursor.as_deref())?;
+    let alerts = state.db.get_all_alerts(&query.filter()?, after, limit).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::page(alerts, "All alerts fetched")))
+}
+
+// Subscription endpoints, authorized by the signed token in the email links rather than
+// by a login
+#[utoipa::path(
+    get,
+    path = "/api/subscription",
+    tag = "subscription",
+    summary = "Get the subscription of a preferences link",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "User and preferences", body = ApiResponse<UserWithPreferences>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+)]
+async fn get_subscription(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = state.tokens.verify(&query.token, TokenPurpose::Preferences)?;
+    let user = state
+        .db
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let preferences = state.db.get_user_preferences(user_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        UserWithPreferences { user, preferences },
+        "Subscription fetched",
+    )))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/subscription/preferences",
+    tag = "subscription",
+    summary = "Update preferences from a preferences link",
+    params(TokenQuery),
+    request_body = UpdatePreferencesRequest,
+    responses(
+        (status = 200, description = "Updated preferences", body = ApiResponse<UserPreferences>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+)]
+async fn update_subscription_preferences(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+    req: web::Json<UpdatePreferencesRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = state.tokens.verify(&query.token, TokenPurpose::Preferences)?;
+    req.validate()
+        .map_err(|e| AppError::Validation(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(alerts, "All alerts fetched")))
+    let preferences = state.db.update_user_preferences(user_id, &req).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        preferences,
+        "Preferences updated successfully",
+    )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/subscription/pause",
+    tag = "subscription",
+    summary = "Stop alerts",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "User paused", body = ApiResponse<User>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Not possible in the current status, e.g. an unconfirmed address", body = ErrorBody),
+    ),
+)]
+async fn pause_subscription(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = state.tokens.verify(&query.token, TokenPurpose::Preferences)?;
+    let user = set_subscription_status(&state, user_id, "paused").await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(user, "Alerts paused")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/subscription/resume",
+    tag = "subscription",
+    summary = "Start alerts again",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "User active", body = ApiResponse<User>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Not possible in the current status, e.g. an unconfirmed address", body = ErrorBody),
+    ),
+)]
+async fn resume_subscription(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = state.tokens.verify(&query.token, TokenPurpose::Preferences)?;
+    let user = set_subscription_status(&state, user_id, "active").await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(user, "Alerts resumed")))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/subscription",
+    tag = "subscription",
+    summary = "Delete the account of an email link",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "User deleted", body = ApiResponse<serde_json::Value>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+)]
+async fn delete_subscription(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    // Either link from an email may be used to remove the subscription entirely
+    let user_id = state
+        .tokens
+        .verify(&query.token, TokenPurpose::Preferences)
+        .or_else(|_| state.tokens.verify(&query.token, TokenPurpose::Unsubscribe))?;
+
+    if !state.db.delete_user(user_id).await? {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::<()>::success((), "Subscription deleted")))
+}
+
+// Mail clients and link scanners may fetch the unsubscribe URL on their own, so a GET
+// only shows a confirmation form; the actual change happens on POST.
+#[utoipa::path(
+    get,
+    path = "/api/subscription/unsubscribe",
+    tag = "subscription",
+    summary = "Unsubscribe confirmation page",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "HTML page with an unsubscribe form", content_type = "text/html", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+)]
+async fn unsubscribe_page(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    state.tokens.verify(&query.token, TokenPurpose::Unsubscribe)?;
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html>
+<body style="font-family: Arial, sans-serif; padding: 20px;">
+    <h2>Unsubscribe from weather alerts?</h2>
+    <p>You will stop receiving weather alert emails. You can resume them at any time from
+    the preferences link in any earlier email.</p>
+    <form method="post" action="/api/subscription/unsubscribe?token={}">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+        query.token
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(body))
+}
+
+/// RFC 8058 one-click unsubscribe target (`List-Unsubscribe-Post`)
+#[utoipa::path(
+    post,
+    path = "/api/subscription/unsubscribe",
+    tag = "subscription",
+    summary = "One-click unsubscribe (RFC 8058)",
+    params(TokenQuery),
+    responses(
+        (status = 200, description = "User paused", body = ApiResponse<User>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Not possible in the current status, e.g. an unconfirmed address", body = ErrorBody),
+    ),
+)]
+async fn one_click_unsubscribe(
+    state: web::Data<AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = state.tokens.verify(&query.token, TokenPurpose::Unsubscribe)?;
+    let user = set_subscription_status(&state, user_id, "paused").await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        user,
+        "You have been unsubscribed from weather alerts",
+    )))
+}
+
+async fn set_subscription_status(
+    state: &AppState,
+    user_id: Uuid,
+    status: &str,
+) -> Result<User, AppError> {
+    let user = state
+        .db
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    if user.status == status {
+        return Ok(user);
+    }
+    check_subscription_change(&user.status, status)?;
+
+    // The status is checked again in the update in case it changed in between
+    state
+        .db
+        .set_user_status(user_id, &user.status, status)
+        .await?
+        .ok_or_else(|| AppError::Conflict("The subscription changed, please try again".to_string()))
+}
+
+/// Email links may only pause active users and resume paused ones. Pending users have to
+/// confirm their address first, so a link from an earlier email can neither activate
+/// them nor keep them out of the cleanup of unconfirmed registrations.
+fn check_subscription_change(current: &str, status: &str) -> Result<(), AppError> {
+    let allowed = matches!((current, status), ("active", "paused") | ("paused", "active"));
+    if allowed {
+        return Ok(());
+    }
+
+    if current == "pending" {
+        return Err(AppError::Conflict(
+            "Please confirm your email address first".to_string(),
+        ));
+    }
+    Err(AppError::Conflict(format!(
+        "A subscription that is {} cannot be changed to {}",
+        current, status
+    )))
+}
+
+// Admin endpoints
+#[utoipa::path(
+    get,
+    path = "/api/admin/outbox",
+    tag = "admin",
+    summary = "List outbox messages by status",
+    params(OutboxQuery),
+    responses(
+        (status = 200, description = "Outbox messages", body = ApiResponse<Vec<OutboxMessage>>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn get_outbox_messages(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    query: web::Query<OutboxQuery>,
+) -> Result<HttpResponse, AppError> {
+    admin.audit(&state.db, "outbox.list", None).await?;
+
+    let status = query.status.as_deref().unwrap_or("dead");
+    if !["pending", "processing", "sent", "dead", "suppressed"].contains(&status) {
+        return Err(AppError::Validation(format!("Unknown outbox status: {}", status)));
+    }
+
+    let limit = pagination::page_size(query.limit, 50);
+    let messages = state.db.get_outbox_messages(status, limit).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(messages, "Outbox messages fetched")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/outbox/{message_id}/requeue",
+    tag = "admin",
+    summary = "Requeue a dead-lettered message",
+    params(("message_id" = Uuid, Path, description = "Outbox message id")),
+    responses(
+        (status = 200, description = "Message requeued", body = ApiResponse<OutboxMessage>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn requeue_outbox_message(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    message_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    admin
+        .audit(&state.db, "outbox.requeue", Some(&message_id.to_string()))
+        .await?;
+
+    let message = state
+        .db
+        .requeue_dead_message(*message_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Dead-lettered message not found".to_string()))?;
+
+    info!("♻️ Outbox message {} requeued", message.id);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(message, "Message requeued for delivery")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/suppressions",
+    tag = "admin",
+    summary = "List suppressed addresses",
+    params(LimitQuery),
+    responses(
+        (status = 200, description = "Suppressions", body = ApiResponse<Vec<Suppression>>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn get_suppressions(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    query: web::Query<LimitQuery>,
+) -> Result<HttpResponse, AppError> {
+    admin.audit(&state.db, "suppressions.list", None).await?;
+
+    let limit = pagination::page_size(query.limit, 100);
+    let suppressions = state.db.get_suppressions(limit).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(suppressions, "Suppressions fetched")))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/suppressions/{email}",
+    tag = "admin",
+    summary = "Lift a suppression",
+    params(("email" = String, Path, description = "Suppressed address")),
+    responses(
+        (status = 200, description = "Suppression removed", body = ApiResponse<serde_json::Value>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn remove_suppression(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    email: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    admin
+        .audit(&state.db, "suppressions.remove", Some(&email))
+        .await?;
+
+    if !state.db.remove_suppression(&email).await? {
+        return Err(AppError::NotFound("Address is not suppressed".to_string()));
+    }
+
+    info!("✉️ Suppression lifted for {}", email);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success((), "Suppression removed")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/preview/{user_id}",
+    tag = "admin",
+    summary = "Render a notification without sending it",
+    params(("user_id" = Uuid, Path, description = "User id"), PreviewQuery),
+    responses(
+        (status = 200, description = "All formats as JSON, or with `format` the raw HTML, text or Markdown", body = ApiResponse<NotificationPreview>),
+        (status = 400, description = "Invalid input", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn preview_notification(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    user_id: web::Path<Uuid>,
+    query: web::Query<PreviewQuery>,
+) -> Result<HttpResponse, AppError> {
+    admin
+        .audit(&state.db, "notifications.preview", Some(&user_id.to_string()))
+        .await?;
+
+    let preview = preview::render(
+        &state.db,
+        &state.composer,
+        *user_id,
+        query.kind.unwrap_or(PreviewKind::Alert),
+        query.weather_id,
+    )
+    .await?;
+
+    // A single format is returned as-is, so HTML can be opened directly in a browser
+    if let Some(format) = query.format {
+        let (content_type, body) = preview.format(format);
+        return Ok(HttpResponse::Ok().content_type(content_type).body(body));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(preview, "Notification rendered")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    tag = "admin",
+    summary = "Read the admin audit log",
+    params(LimitQuery),
+    responses(
+        (status = 200, description = "Audit entries, newest first", body = ApiResponse<Vec<AdminAuditEntry>>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn get_admin_audit_log(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    query: web::Query<LimitQuery>,
+) -> Result<HttpResponse, AppError> {
+    admin.audit(&state.db, "audit.list", None).await?;
+
+    let limit = pagination::page_size(query.limit, 100);
+    let entries = state.db.get_admin_audit_log(limit).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entries, "Audit log fetched")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/export/{dataset}",
+    tag = "admin",
+    summary = "Export weather or alert data",
+    params(("dataset" = ExportDataset, Path, description = "Data to export"), ExportQuery),
+    responses(
+        (status = 200, description = "Streamed CSV, NDJSON or Parquet file", content(
+            (String = "text/csv"),
+            (String = "application/x-ndjson"),
+            (Vec<u8> = "application/vnd.apache.parquet"),
+        )),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed for these credentials", body = ErrorBody),
+    ),
+    security(("admin_key" = []), ("bearer_token" = [])),
+)]
+async fn export_data(
+    state: web::Data<AppState>,
+    admin: AdminUser,
+    dataset: web::Path<ExportDataset>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, AppError> {
+    let dataset = dataset.into_inner();
+    let query = query.into_inner();
+    let format = query.format.unwrap_or(ExportFormat::Csv);
+    admin
+        .audit(
+            &state.db,
+            &format!("export.{}", dataset.as_str()),
+            Some(format.extension()),
+        )
+        .await?;
+
+    let filter = ExportFilter {
+        city: query.city,
+        country: query.country,
+        from: query.from,
+        to: query.to,
+    };
+    let filename = format!(
+        "{}-{}.{}",
+        dataset.as_str(),
+        Utc::now().format("%Y%m%d%H%M%S"),
+        format.extension()
+    );
+    let chunks = export::spawn(state.db.clone(), dataset, format, filter);
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .streaming(chunks))
+}
+
+// Webhook endpoints
+#[utoipa::path(
+    post,
+    path = "/api/webhooks/mail",
+    tag = "webhooks",
+    summary = "Report a bounce or complaint",
+    request_body = MailEvent,
+    responses(
+        (status = 200, description = "Event processed", body = ApiResponse<serde_json::Value>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    ),
+    security(("webhook_secret" = [])),
+)]
+async fn mail_webhook(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    event: web::Json<MailEvent>,
+) -> Result<HttpResponse, AppError> {
+    // Without a configured secret anyone could suppress arbitrary addresses
+    let secret = state
+        .config
+        .mail_webhook_secret
+        .as_deref()
+        .ok_or_else(|| AppError::Unauthorized("Mail webhook is not configured".to_string()))?;
+    let provided = req
+        .headers()
+        .get("X-Webhook-Secret")
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    if !tokens::constant_time_eq(provided, secret.as_bytes()) {
+        return Err(AppError::Unauthorized("Invalid webhook secret".to_string()));
+    }
+
+    let event = event.into_inner();
+    let reason = event.reason.as_deref().unwrap_or("no reason given");
+
+    let suppression = match event.event {
+        MailEventKind::Complaint => Some("complaint"),
+        MailEventKind::Bounce if event.bounce_type.as_deref() == Some("transient") => None,
+        MailEventKind::Bounce => Some("hard_bounce"),
+    };
+
+    match suppression {
+        Some(kind) => {
+            state.db.suppress_email(&event.email, kind, Some(reason)).await?;
+            // A transient bounce may still be delivered on a later try, so only permanent
+            // failures mark the alert
+            if let Some(message_id) = &event.message_id {
+                let status = format!("{:?} reported: {}", event.event, reason);
+                state.db.mark_alert_bounced(message_id, &status).await?;
+            }
+        }
+        None => info!("📭 Transient bounce for {}: {}", event.email, reason),
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success((), "Event processed")))
 }
 
 // Query parameters
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LimitQuery {
+    /// Maximum number of entries (at most 500)
+    limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UserQuery {
+    city: Option<String>,
+    /// ISO 3166 alpha-2 code
+    country: Option<String>,
+    /// Page size (default 100, at most 500)
+    limit: Option<i64>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct HistoryQuery {
+    /// Aggregate readings per hour, day or week instead of returning them (over at most
+    /// 31 days, 366 days or 260 weeks)
+    bucket: Option<HistoryBucket>,
+    /// Start of the range, inclusive (default: 24 hours, or the bucket's default range, before `to`)
+    from: Option<DateTime<Utc>>,
+    /// End of the range, exclusive (default: now)
+    to: Option<DateTime<Utc>>,
+    /// Page size for raw readings (default 100, at most 500)
     limit: Option<i64>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct AlertQuery {
+    /// `high_temperature`, `low_temperature`, `rain`, `snow` or `storm`
+    kind: Option<String>,
+    /// `minor`, `moderate` or `severe`
+    severity: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    /// Page size (at most 500)
     limit: Option<i64>,
-}
\ No newline at end of file
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+}
+
+impl AlertQuery {
+    fn filter(&self) -> Result<AlertFilter, AppError> {
+        AlertFilter::new(self.kind.as_deref(), self.severity.as_deref(), self.from, self.to)
+    }
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OutboxQuery {
+    /// `pending`, `processing`, `sent`, `dead` (default) or `suppressed`
+    status: Option<String>,
+    /// Maximum number of messages (default 50, at most 500)
+    limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PreviewQuery {
+    kind: Option<PreviewKind>,
+    /// Reading to render an alert from (default: latest for the user's city)
+    weather_id: Option<Uuid>,
+    /// Return only this format, with its own content type
+    format: Option<PreviewFormat>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery {
+    /// Default `csv`
+    format: Option<ExportFormat>,
+    city: Option<String>,
+    country: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EventQuery {
+    /// Only events for this city (case-insensitive)
+    city: Option<String>,
+    /// Token from `POST /api/events/token`, for clients that can't send an
+    /// `Authorization` header
+    token: Option<String>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TokenQuery {
+    /// Signed token from the email link
+    token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_pause_active_and_resume_paused_users() {
+        assert!(check_subscription_change("active", "paused").is_ok());
+        assert!(check_subscription_change("paused", "active").is_ok());
+    }
+
+    #[test]
+    fn links_cannot_resume_or_pause_pending_users() {
+        assert!(matches!(
+            check_subscription_change("pending", "active"),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            check_subscription_change("pending", "paused"),
+            Err(AppError::Conflict(_))
+        ));
+    }
+}
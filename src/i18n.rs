@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+pub const DEFAULT_LOCALE: &str = "en";
+
+// Message catalogs shipped with the binary, one flat `key -> message` map per locale.
+// Messages may contain `{name}` placeholders.
+const CATALOG_SOURCES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("hi", include_str!("../locales/hi.json")),
+    ("de", include_str!("../locales/de.json")),
+];
+
+type Catalog = HashMap<String, String>;
+
+fn catalogs() -> &'static HashMap<&'static str, Catalog> {
+    static CATALOGS: OnceLock<HashMap<&'static str, Catalog>> = OnceLock::new();
+    CATALOGS.get_or_init(|| {
+        CATALOG_SOURCES
+            .iter()
+            .map(|(locale, source)| {
+                let catalog = serde_json::from_str(source)
+                    .unwrap_or_else(|e| panic!("invalid message catalog for {}: {}", locale, e));
+                (*locale, catalog)
+            })
+            .collect()
+    })
+}
+
+pub fn supported_locales() -> Vec<&'static str> {
+    CATALOG_SOURCES.iter().map(|(locale, _)| *locale).collect()
+}
+
+/// Maps a requested locale such as `de`, `DE` or `de-AT` onto a supported one.
+pub fn normalize(locale: &str) -> Option<&'static str> {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    CATALOG_SOURCES
+        .iter()
+        .map(|(supported, _)| *supported)
+        .find(|supported| *supported == language)
+}
+
+/// Looks up `key` in the catalog for `locale`, falling back to English and finally to the
+/// key itself, then fills in the `{name}` placeholders from `args`.
+pub fn t(locale: &str, key: &str, args: &[(&str, &str)]) -> String {
+    let catalogs = catalogs();
+    let message = catalogs
+        .get(locale)
+        .and_then(|catalog| catalog.get(key))
+        .or_else(|| catalogs.get(DEFAULT_LOCALE).and_then(|catalog| catalog.get(key)))
+        .map(String::as_str)
+        .unwrap_or(key);
+
+    args.iter().fold(message.to_string(), |message, (name, value)| {
+        message.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_maps_variants_onto_supported_locales() {
+        assert_eq!(normalize("de"), Some("de"));
+        assert_eq!(normalize("DE"), Some("de"));
+        assert_eq!(normalize("de-AT"), Some("de"));
+        assert_eq!(normalize("hi_IN"), Some("hi"));
+        assert_eq!(normalize("en-us"), Some("en"));
+    }
+
+    #[test]
+    fn normalize_rejects_unsupported_locales() {
+        assert_eq!(normalize("fr"), None);
+        assert_eq!(normalize("fr-DE"), None);
+        assert_eq!(normalize(""), None);
+    }
+
+    #[test]
+    fn t_fills_in_placeholders() {
+        let message = t("de", "alert.high_temp", &[("current", "31.5"), ("limit", "30")]);
+        assert_eq!(message, "🌡️ Hitzewarnung! Aktuell: 31.5°C (Ihr Grenzwert: 30°C)");
+    }
+
+    #[test]
+    fn t_falls_back_to_english_then_to_the_key() {
+        let english = t("en", "alert.rain", &[("conditions", "light rain")]);
+        assert_eq!(t("fr", "alert.rain", &[("conditions", "light rain")]), english);
+        assert_eq!(t("de", "no.such.key", &[]), "no.such.key");
+    }
+
+    #[test]
+    fn t_leaves_unknown_placeholders_alone() {
+        let message = t("en", "alert.rain", &[]);
+        assert!(message.contains("{conditions}"));
+    }
+
+    #[test]
+    fn every_catalog_has_the_english_keys() {
+        let english = &catalogs()[DEFAULT_LOCALE];
+        for locale in supported_locales() {
+            let catalog = &catalogs()[locale];
+            for key in english.keys() {
+                assert!(catalog.contains_key(key), "{} is missing {}", locale, key);
+            }
+        }
+    }
+}
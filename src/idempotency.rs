@@ -0,0 +1,339 @@
+use crate::auth::{bearer_token, hash_token};
+use crate::db::Database;
+use crate::error::AppError;
+use crate::models::IdempotencyKey;
+use crate::AppState;
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, Error, HttpResponse};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::rc::Rc;
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+/// Set on responses that were replayed rather than produced by running the request
+pub const IDEMPOTENT_REPLAYED: &str = "Idempotent-Replayed";
+
+const MAX_KEY_LEN: usize = 255;
+// Response headers that describe the original transfer rather than the response
+const TRANSFER_HEADERS: [&str; 5] = [
+    "content-length",
+    "content-encoding",
+    "transfer-encoding",
+    "connection",
+    "date",
+];
+
+/// Middleware making `POST` and `PUT` requests with an `Idempotency-Key` header safe to
+/// retry. The first response (unless it is a server error) is stored for
+/// `IDEMPOTENCY_KEY_TTL_HOURS` and replayed for repeats with the same key and body; reusing
+/// a key with a different body gets `422`, a repeat while the first request still runs
+/// `409`. Wrapped around single routes, and since responses are stored, never around ones
+/// whose responses carry secrets, such as sign-in.
+pub struct Idempotency;
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = IdempotencyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct IdempotencyMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let key = req
+            .headers()
+            .get(IDEMPOTENCY_KEY)
+            .map(|value| value.to_str().map(str::to_string));
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+
+        let (Some(key), Some(state), true) = (key, state, is_mutating(req.method())) else {
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_boxed_body()) });
+        };
+
+        Box::pin(async move {
+            let key = match key {
+                Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key,
+                _ => {
+                    let error = AppError::Validation(format!(
+                        "{} must be 1 to {} visible ASCII characters",
+                        IDEMPOTENCY_KEY, MAX_KEY_LEN
+                    ));
+                    return Ok(req.error_response(error));
+                }
+            };
+
+            // Read the body to fingerprint it, then hand it back to the handler
+            let body = match req.extract::<web::Bytes>().await {
+                Ok(body) => body,
+                Err(e) => return Ok(req.error_response(e)),
+            };
+            req.set_payload(Payload::from(body.clone()));
+
+            let scope = request_scope(&req, state.config.rate_limit_trust_proxy);
+            let request_hash = hex_digest(&body);
+            let ttl_hours = state.config.idempotency_key_ttl_hours;
+
+            match claim_or_replay(&state.db, &scope, &key, &request_hash, ttl_hours).await {
+                Ok(None) => {}
+                Ok(Some(replay)) => return Ok(req.into_response(replay)),
+                Err(e) => return Ok(req.error_response(e)),
+            }
+
+            let res = match service.call(req).await {
+                Ok(res) => res,
+                Err(e) => {
+                    release(&state.db, &scope, &key).await;
+                    return Err(e);
+                }
+            };
+            // A server error says nothing about the request, so a retry should run it again
+            if res.status().is_server_error() {
+                release(&state.db, &scope, &key).await;
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let (http_req, response) = res.into_parts();
+            let (head, body) = response.into_parts();
+            let body = match body::to_bytes(body).await {
+                Ok(body) => body,
+                Err(e) => {
+                    release(&state.db, &scope, &key).await;
+                    let error = AppError::Internal(format!("Could not buffer response: {}", e.into()));
+                    return Ok(ServiceResponse::from_err(error, http_req));
+                }
+            };
+
+            let headers: Vec<(String, String)> = head
+                .headers()
+                .iter()
+                .filter(|(name, _)| !TRANSFER_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect();
+            if let Err(e) = state
+                .db
+                .complete_idempotency_key(
+                    &scope,
+                    &key,
+                    head.status().as_u16() as i32,
+                    &serde_json::json!(headers),
+                    &body,
+                )
+                .await
+            {
+                // The response is still good; only a repeat will run the request again
+                warn!("⚠️ Could not store response for {} {}: {}", IDEMPOTENCY_KEY, key, e);
+                release(&state.db, &scope, &key).await;
+            }
+
+            Ok(ServiceResponse::new(http_req, head.set_body(body).map_into_boxed_body()))
+        })
+    }
+}
+
+fn is_mutating(method: &Method) -> bool {
+    method == Method::POST || method == Method::PUT
+}
+
+/// Keys are only unique per route and client: the method and path of the request plus a
+/// hash of its bearer token or admin key, or for anonymous requests their IP address and a
+/// hash of the query string (which may hold a signed link token)
+fn request_scope(req: &ServiceRequest, trust_proxy: bool) -> String {
+    let credential = bearer_token(req.request())
+        .map(|token| format!("token:{}", hash_token(&token)))
+        .or_else(|| {
+            req.headers()
+                .get("X-Admin-Key")
+                .map(|key| format!("admin_key:{}", hash_token(&String::from_utf8_lossy(key.as_bytes()))))
+        })
+        .unwrap_or_else(|| {
+            let info = req.connection_info();
+            let ip = if trust_proxy {
+                info.realip_remote_addr()
+            } else {
+                info.peer_addr()
+            };
+            format!(
+                "anonymous:{}:{}",
+                ip.unwrap_or("unknown"),
+                hash_token(req.query_string())
+            )
+        });
+
+    format!("{} {} {}", req.method(), req.path(), credential)
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Claims `key` for this request (`None`), or answers a repeat of an earlier one
+async fn claim_or_replay(
+    db: &Database,
+    scope: &str,
+    key: &str,
+    request_hash: &str,
+    ttl_hours: i64,
+) -> Result<Option<HttpResponse>, AppError> {
+    if db.claim_idempotency_key(scope, key, request_hash, ttl_hours).await? {
+        return Ok(None);
+    }
+
+    let record = db.get_idempotency_key(scope, key).await?;
+    answer_repeat(record, request_hash).map(Some)
+}
+
+/// The answer to a request whose key was already taken by `record`
+fn answer_repeat(record: Option<IdempotencyKey>, request_hash: &str) -> Result<HttpResponse, AppError> {
+    match record {
+        Some(record) if record.request_hash != request_hash => Err(AppError::Unprocessable(format!(
+            "{} was already used for a request with a different body",
+            IDEMPOTENCY_KEY
+        ))),
+        Some(record) if record.status == "completed" => Ok(replay(record)),
+        _ => Err(AppError::Conflict(format!(
+            "A request with this {} is still being processed",
+            IDEMPOTENCY_KEY
+        ))),
+    }
+}
+
+fn replay(record: IdempotencyKey) -> HttpResponse {
+    let status = record
+        .status_code
+        .and_then(|code| StatusCode::from_u16(code as u16).ok())
+        .unwrap_or(StatusCode::OK);
+    let headers: Vec<(String, String)> = record
+        .response_headers
+        .and_then(|headers| serde_json::from_value(headers).ok())
+        .unwrap_or_default();
+
+    let mut response = HttpResponse::build(status);
+    for header in headers {
+        response.append_header(header);
+    }
+    response
+        .insert_header((IDEMPOTENT_REPLAYED, "true"))
+        .body(record.response_body.unwrap_or_default())
+}
+
+async fn release(db: &Database, scope: &str, key: &str) {
+    if let Err(e) = db.release_idempotency_key(scope, key).await {
+        warn!("⚠️ Could not release {} {}: {}", IDEMPOTENCY_KEY, key, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::test::TestRequest;
+    use chrono::Utc;
+
+    fn record(request_hash: &str, status: &str) -> IdempotencyKey {
+        IdempotencyKey {
+            scope: "POST /api/users anonymous".to_string(),
+            key: "key-1".to_string(),
+            request_hash: request_hash.to_string(),
+            status: status.to_string(),
+            status_code: (status == "completed").then_some(201),
+            response_headers: Some(serde_json::json!([["content-type", "application/json"]])),
+            response_body: Some(b"{\"success\":true}".to_vec()),
+            created_at: Utc::now(),
+            expires_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn different_body_is_unprocessable() {
+        let result = answer_repeat(Some(record("aaa", "completed")), "bbb");
+        assert!(matches!(result, Err(AppError::Unprocessable(_))));
+
+        // Even while the first request still runs
+        let result = answer_repeat(Some(record("aaa", "in_progress")), "bbb");
+        assert!(matches!(result, Err(AppError::Unprocessable(_))));
+    }
+
+    #[test]
+    fn completed_request_is_replayed() {
+        let response = answer_repeat(Some(record("aaa", "completed")), "aaa").unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers().get(IDEMPOTENT_REPLAYED).unwrap(), "true");
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+    }
+
+    #[test]
+    fn running_request_is_a_conflict() {
+        let result = answer_repeat(Some(record("aaa", "in_progress")), "aaa");
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+
+        // Released between the claim and the lookup
+        assert!(matches!(answer_repeat(None, "aaa"), Err(AppError::Conflict(_))));
+    }
+
+    #[test]
+    fn anonymous_scope_includes_ip_and_query() {
+        let req = |ip: &str, uri: &str| {
+            TestRequest::put()
+                .uri(uri)
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .to_srv_request()
+        };
+
+        let scope = request_scope(&req("10.0.0.1", "/api/subscription/preferences?token=a"), false);
+        assert!(scope.starts_with("PUT /api/subscription/preferences anonymous:10.0.0.1:"));
+        assert!(!scope.contains("token=a"));
+
+        assert_ne!(
+            scope,
+            request_scope(&req("10.0.0.2", "/api/subscription/preferences?token=a"), false)
+        );
+        assert_ne!(
+            scope,
+            request_scope(&req("10.0.0.1", "/api/subscription/preferences?token=b"), false)
+        );
+    }
+
+    #[test]
+    fn authenticated_scope_uses_the_token_hash() {
+        let req = TestRequest::post()
+            .uri("/api/users")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_srv_request();
+
+        let scope = request_scope(&req, false);
+        assert_eq!(scope, format!("POST /api/users token:{}", hash_token("secret")));
+    }
+}
@@ -2,18 +2,38 @@ use actix_files as fs;
 use actix_web::{middleware, web, App, HttpServer};
 use clap::{Parser, Subcommand};
 use log::info;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio_cron_scheduler::{Job, JobScheduler};
 
+mod auth;
+mod chart;
 mod config;
 mod db;
 mod email;
 mod error;
+mod events;
+mod export;
+mod fetch_guard;
+mod graphql;
 mod handlers;
+mod i18n;
+mod idempotency;
+// WeatherResponse is part of the public model set but nothing builds it yet
+#[allow(dead_code)]
 mod models;
+mod outbox;
+mod pagination;
+mod preview;
+mod rate_limit;
+mod templates;
+mod tokens;
 mod weather;
 
+// Job runs without progress for this long are taken to be interrupted
+const STALE_JOB_RUN_MINUTES: i64 = 15;
+
 use crate::config::Config;
 use crate::db::Database;
 use crate::error::AppError;
@@ -34,11 +54,52 @@ enum Commands {
     },
     /// Manually fetch weather for all users
     FetchWeather,
+    /// Send digests of alerts deferred by the daily cap now
+    SendDigests,
     /// Send test email
     TestEmail {
         #[arg(short, long)]
         to: String,
     },
+    /// Render a notification for a user without sending it
+    Preview {
+        #[arg(short, long)]
+        user_id: uuid::Uuid,
+        #[arg(short, long, value_enum, default_value = "alert")]
+        kind: preview::PreviewKind,
+        /// Weather reading to render an alert from (default: latest for the user's city)
+        #[arg(short, long)]
+        weather_id: Option<uuid::Uuid>,
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: preview::PreviewFormat,
+    },
+    /// Export weather or alert data to a file (or stdout)
+    Export {
+        #[arg(short, long, value_enum)]
+        dataset: export::ExportDataset,
+        #[arg(short, long, value_enum, default_value = "csv")]
+        format: export::ExportFormat,
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+        #[arg(long)]
+        city: Option<String>,
+        #[arg(long)]
+        country: Option<String>,
+        /// Start of the time range (RFC 3339, inclusive)
+        #[arg(long)]
+        from: Option<chrono::DateTime<chrono::Utc>>,
+        /// End of the time range (RFC 3339, exclusive)
+        #[arg(long)]
+        to: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Grant or revoke the admin role (`user` or `admin`)
+    SetRole {
+        #[arg(short, long)]
+        email: String,
+        #[arg(short, long)]
+        role: String,
+    },
     /// Initialize database schema
     InitDb,
     /// List all scheduled jobs
@@ -50,7 +111,12 @@ pub struct AppState {
     pub db: Database,
     pub config: Config,
     pub weather_client: weather::WeatherClient,
-    pub email_client: email::EmailClient,
+    pub events: events::EventBus,
+    pub graphql: graphql::ApiSchema,
+    pub fetch_guard: fetch_guard::FetchGuard,
+    pub rate_limiter: rate_limit::RateLimiter,
+    pub tokens: tokens::TokenSigner,
+    pub composer: email::EmailComposer,
 }
 
 #[actix_web::main]
@@ -66,12 +132,7 @@ async fn main() -> Result<(), AppError> {
     // Initialize database
     let db = Database::new(&config.database_url).await?;
     let weather_client = weather::WeatherClient::new(config.weather_api_key.clone());
-    let email_client = email::EmailClient::new(
-        &config.smtp_host,
-        config.smtp_port,
-        &config.smtp_username,
-        &config.smtp_password,
-    )?;
+    let email_client = email::EmailClient::new(&config)?;
 
     match cli.command {
         Some(Commands::Serve { port }) => {
@@ -79,16 +140,89 @@ async fn main() -> Result<(), AppError> {
         }
         Some(Commands::FetchWeather) => {
             info!("📡 Manually fetching weather...");
-            fetch_and_alert(&db, &weather_client, &email_client).await?;
-            info!("✅ Weather fetch completed!");
+            let run = db.create_job_run(models::JOB_WEATHER_FETCH, "cli", None).await?;
+            // Nobody listens to the events of a CLI fetch
+            let events = events::EventBus::new();
+            fetch_and_alert(&db, &config, &weather_client, &events, run.id).await?;
+            let delivered = outbox::drain(&db, &email_client, &config).await?;
+            info!("✅ Weather fetch completed! {} notification(s) processed", delivered);
+        }
+        Some(Commands::SendDigests) => {
+            info!("🗞️ Sending alert digests...");
+            let digests = send_digests(&db, &config).await?;
+            let delivered = outbox::drain(&db, &email_client, &config).await?;
+            info!("✅ {} digest(s) queued, {} notification(s) processed", digests, delivered);
         }
         Some(Commands::TestEmail { to }) => {
+            if db.is_suppressed(&to).await? {
+                return Err(AppError::Validation(format!(
+                    "{} is on the suppression list; remove it first to send a test email",
+                    to
+                )));
+            }
             info!("📧 Sending test email to {}", to);
             email_client
                 .send_test_email(&to, "Weather Alert Test")
                 .await?;
             info!("✅ Test email sent!");
         }
+        Some(Commands::Preview {
+            user_id,
+            kind,
+            weather_id,
+            format,
+        }) => {
+            let preview =
+                preview::render(&db, email_client.composer(), user_id, kind, weather_id).await?;
+            println!("Subject: {}\n", preview.subject);
+            println!("{}", preview.format(format).1);
+        }
+        Some(Commands::Export {
+            dataset,
+            format,
+            output,
+            city,
+            country,
+            from,
+            to,
+        }) => {
+            use futures::StreamExt;
+            use std::io::Write;
+
+            let filter = models::ExportFilter {
+                city,
+                country,
+                from,
+                to,
+            };
+            let mut out: Box<dyn Write> = match &output {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+
+            let mut chunks = export::spawn(db.clone(), dataset, format, filter);
+            while let Some(chunk) = chunks.next().await {
+                out.write_all(&chunk?)?;
+            }
+            out.flush()?;
+
+            if let Some(path) = output {
+                info!("✅ Export written to {}", path.display());
+            }
+        }
+        Some(Commands::SetRole { email, role }) => {
+            if !["user", "admin"].contains(&role.as_str()) {
+                return Err(AppError::Validation(format!(
+                    "Unknown role: {} (expected user or admin)",
+                    role
+                )));
+            }
+            db.set_user_role(&email, &role)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("No user with email {}", email)))?;
+            db.record_admin_action("cli", "users.set_role", Some(&format!("{}={}", email, role)))
+                .await?;
+        }
         Some(Commands::InitDb) => {
             info!("🗄️  Initializing database schema...");
             db.init_schema().await?;
@@ -116,27 +250,49 @@ async fn start_server(
 
     let scheduler = JobScheduler::new().await?;
     let scheduler = Arc::new(Mutex::new(scheduler));
+    let events = events::EventBus::new();
+    let fetch_guard = fetch_guard::FetchGuard::default();
+
+    // Runs cut short by a crash or restart would otherwise show as running forever
+    match db.fail_stale_job_runs(STALE_JOB_RUN_MINUTES).await {
+        Ok(0) => {}
+        Ok(n) => info!("🧹 Marked {} interrupted job run(s) as failed", n),
+        Err(e) => log::error!("❌ Could not clean up interrupted job runs: {}", e),
+    }
 
     // Setup CRON job
     setup_weather_cron(
         scheduler.clone(),
         db.clone(),
+        config.clone(),
         weather_client.clone(),
-        email_client.clone(),
+        events.clone(),
+        fetch_guard.clone(),
     )
     .await?;
 
+    setup_cleanup_cron(scheduler.clone(), db.clone(), config.clone()).await?;
+    setup_digest_cron(scheduler.clone(), db.clone(), config.clone()).await?;
+
     {
         let sched = scheduler.lock().await;
         sched.start().await?;
         info!("⏰ CRON scheduler started - Weather fetch every 2 hours");
     }
 
+    let composer = email_client.composer().clone();
+    tokio::spawn(outbox::run_worker(db.clone(), email_client, config.clone()));
+
     let app_state = AppState {
         db: db.clone(),
+        composer,
+        tokens: tokens::TokenSigner::new(&config.app_secret),
         config: config.clone(),
         weather_client,
-        email_client,
+        events,
+        graphql: graphql::build_schema(db.clone()),
+        fetch_guard,
+        rate_limiter: rate_limit::RateLimiter::new(&config),
     };
 
     HttpServer::new(move || {
@@ -157,8 +313,10 @@ async fn start_server(
 async fn setup_weather_cron(
     scheduler: Arc<Mutex<JobScheduler>>,
     db: Database,
+    config: Config,
     weather_client: weather::WeatherClient,
-    email_client: email::EmailClient,
+    events: events::EventBus,
+    fetch_guard: fetch_guard::FetchGuard,
 ) -> Result<(), AppError> {
     let sched = scheduler.lock().await;
 
@@ -166,12 +324,29 @@ async fn setup_weather_cron(
     // For testing every 5 minutes: "0 */5 * * * *"
     let job = Job::new_async("0 0 */2 * * *", move |_uuid, _l| {
         let db = db.clone();
+        let config = config.clone();
         let weather_client = weather_client.clone();
-        let email_client = email_client.clone();
+        let events = events.clone();
+        let fetch_guard = fetch_guard.clone();
 
         Box::pin(async move {
+            let permit = match fetch_guard.claim() {
+                fetch_guard::Claim::Started(permit) => permit,
+                fetch_guard::Claim::Running(run_id) => {
+                    info!("⏭️ CRON Job: Weather fetch {:?} still running, skipping", run_id);
+                    return;
+                }
+            };
             info!("🌤️  CRON Job: Starting weather fetch...");
-            match fetch_and_alert(&db, &weather_client, &email_client).await {
+            let run = match db.create_job_run(models::JOB_WEATHER_FETCH, "cron", None).await {
+                Ok(run) => run,
+                Err(e) => {
+                    log::error!("❌ CRON Job: Could not record weather fetch run: {}", e);
+                    return;
+                }
+            };
+            permit.set_run(run.id);
+            match fetch_and_alert(&db, &config, &weather_client, &events, run.id).await {
                 Ok(_) => info!("✅ CRON Job: Weather fetch completed successfully"),
                 Err(e) => log::error!("❌ CRON Job: Weather fetch failed: {}", e),
             }
@@ -184,20 +359,140 @@ async fn setup_weather_cron(
     Ok(())
 }
 
+async fn setup_cleanup_cron(
+    scheduler: Arc<Mutex<JobScheduler>>,
+    db: Database,
+    config: Config,
+) -> Result<(), AppError> {
+    let sched = scheduler.lock().await;
+
+    // Hourly: drop registrations whose email was never confirmed, and expired tokens,
+    // sign-in links and idempotency keys; fail job runs that stopped making progress
+    let job = Job::new_async("0 0 * * * *", move |_uuid, _l| {
+        let db = db.clone();
+        let max_age_hours = config.pending_user_ttl_hours;
+
+        Box::pin(async move {
+            match db.delete_expired_pending_users(max_age_hours).await {
+                Ok(0) => {}
+                Ok(n) => info!("🧹 CRON Job: Removed {} unverified registration(s)", n),
+                Err(e) => log::error!("❌ CRON Job: Pending user cleanup failed: {}", e),
+            }
+            match db.delete_expired_api_tokens().await {
+                Ok(0) => {}
+                Ok(n) => info!("🧹 CRON Job: Removed {} expired API token(s)", n),
+                Err(e) => log::error!("❌ CRON Job: API token cleanup failed: {}", e),
+            }
+            match db.delete_expired_login_links().await {
+                Ok(0) => {}
+                Ok(n) => info!("🧹 CRON Job: Removed {} used sign-in link(s)", n),
+                Err(e) => log::error!("❌ CRON Job: Sign-in link cleanup failed: {}", e),
+            }
+            match db.delete_expired_idempotency_keys().await {
+                Ok(0) => {}
+                Ok(n) => info!("🧹 CRON Job: Removed {} expired idempotency key(s)", n),
+                Err(e) => log::error!("❌ CRON Job: Idempotency key cleanup failed: {}", e),
+            }
+            match db.fail_stale_job_runs(STALE_JOB_RUN_MINUTES).await {
+                Ok(0) => {}
+                Ok(n) => info!("🧹 CRON Job: Marked {} interrupted job run(s) as failed", n),
+                Err(e) => log::error!("❌ CRON Job: Job run cleanup failed: {}", e),
+            }
+        })
+    })?;
+
+    sched.add(job).await?;
+    info!("✅ CRON job scheduled: Unverified user cleanup every hour");
+
+    Ok(())
+}
+
+async fn setup_digest_cron(
+    scheduler: Arc<Mutex<JobScheduler>>,
+    db: Database,
+    config: Config,
+) -> Result<(), AppError> {
+    let sched = scheduler.lock().await;
+
+    // Daily at 18:00 UTC: fold alerts held back by the daily cap into one email per user
+    let job = Job::new_async("0 0 18 * * *", move |_uuid, _l| {
+        let db = db.clone();
+        let config = config.clone();
+
+        Box::pin(async move {
+            match send_digests(&db, &config).await {
+                Ok(0) => {}
+                Ok(n) => info!("🗞️ CRON Job: Queued {} alert digest(s)", n),
+                Err(e) => log::error!("❌ CRON Job: Alert digest failed: {}", e),
+            }
+        })
+    })?;
+
+    sched.add(job).await?;
+    info!("✅ CRON job scheduled: Alert digest daily at 18:00 UTC");
+
+    Ok(())
+}
+
+/// Queues one digest email per active user for all of their deferred alerts.
+/// Returns the number of digests queued.
+async fn send_digests(db: &Database, config: &Config) -> Result<usize, AppError> {
+    let mut queued = 0;
+    for user in db.get_users_with_deferred_alerts().await? {
+        // Each user's alerts are marked together with their digest, so a failure leaves
+        // that user's alerts deferred for the next run without affecting the others
+        match db.enqueue_digest(&user, config.outbox_max_attempts).await {
+            Ok(Some(message)) => {
+                info!("📥 Queued digest {} for {}", message.id, user.email);
+                queued += 1;
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("❌ Could not queue digest for {}: {}", user.email, e),
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Fetches the weather of every user city and queues the resulting alerts, recording
+/// progress on the job run `run_id`. The run ends `failed` if the fetch stops on an error.
 async fn fetch_and_alert(
     db: &Database,
+    config: &Config,
     weather_client: &weather::WeatherClient,
-    email_client: &email::EmailClient,
+    events: &events::EventBus,
+    run_id: uuid::Uuid,
+) -> Result<models::JobRun, AppError> {
+    let result = fetch_cities(db, config, weather_client, events, run_id).await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    let run = db.finish_job_run(run_id, error.as_deref()).await?;
+    info!(
+        "🏁 Job run {} {}: {}/{} cities, {} alert(s) queued, {} deferred",
+        run.id, run.status, run.cities_succeeded, run.cities_total, run.alerts_queued, run.alerts_deferred
+    );
+
+    result.map(|_| run)
+}
+
+async fn fetch_cities(
+    db: &Database,
+    config: &Config,
+    weather_client: &weather::WeatherClient,
+    events: &events::EventBus,
+    run_id: uuid::Uuid,
 ) -> Result<(), AppError> {
     // Get all unique cities from users
     let cities = db.get_all_user_cities().await?;
     info!("📍 Found {} unique cities to fetch", cities.len());
+    db.set_job_cities_total(run_id, cities.len() as i32).await?;
+    let total = cities.len();
+    let mut failed = 0;
 
     for city_info in cities {
         info!("🌍 Fetching weather for {}, {}", city_info.city, city_info.country);
 
         // Fetch weather from API
-        match weather_client.get_weather(&city_info.city, &city_info.country).await {
+        match weather_client.get_weather(&city_info.city, &city_info.country, None).await {
             Ok(weather) => {
                 // Store in database
                 db.store_weather_data(&weather).await?;
@@ -205,35 +500,28 @@ async fn fetch_and_alert(
                     "💾 Stored weather: {} - {}°C, {}",
                     city_info.city, weather.temperature, weather.conditions
                 );
-
-                // Check users in this city for alerts
-                let users = db.get_users_by_city(&city_info.city).await?;
-                
-                for user in users {
-                    if let Some(prefs) = db.get_user_preferences(user.id).await? {
-                        let should_alert = check_alert_conditions(&weather, &prefs);
-                        
-                        if let Some(alert_message) = should_alert {
-                            info!("🔔 Sending alert to {}: {}", user.email, alert_message);
-                            
-                            match email_client
-                                .send_weather_alert(&user.email, &city_info.city, &alert_message)
-                                .await
-                            {
-                                Ok(_) => {
-                                    db.log_alert(user.id, "temperature", &alert_message).await?;
-                                    info!("✅ Alert sent to {}", user.email);
-                                }
-                                Err(e) => {
-                                    log::error!("❌ Failed to send alert to {}: {}", user.email, e);
-                                }
-                            }
-                        }
-                    }
-                }
+                events.publish(events::LiveEvent::Weather(weather.clone()));
+
+                // Alerts queued before an error are still counted, since they will be sent
+                let mut counts = AlertCounts::default();
+                let result = alert_city_users(
+                    db,
+                    config,
+                    weather_client,
+                    events,
+                    &city_info,
+                    &weather,
+                    &mut counts,
+                )
+                .await;
+                db.record_job_city(run_id, result.is_ok(), counts.queued, counts.deferred)
+                    .await?;
+                result?;
             }
             Err(e) => {
                 log::error!("❌ Failed to fetch weather for {}: {}", city_info.city, e);
+                db.record_job_city(run_id, false, 0, 0).await?;
+                failed += 1;
             }
         }
 
@@ -241,44 +529,175 @@ async fn fetch_and_alert(
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
 
+    // Single cities may fail, but a run that reached none of them did not work
+    if total > 0 && failed == total {
+        return Err(AppError::WeatherApi(format!(
+            "Weather fetch failed for all {} cities",
+            total
+        )));
+    }
+
+    Ok(())
+}
+
+/// Alerts queued, and deferred by the daily cap, for a city
+#[derive(Debug, Default)]
+struct AlertCounts {
+    queued: i32,
+    deferred: i32,
+}
+
+/// Checks the users of a city against its fresh weather and queues their alerts,
+/// adding them to `counts` as it goes.
+async fn alert_city_users(
+    db: &Database,
+    config: &Config,
+    weather_client: &weather::WeatherClient,
+    events: &events::EventBus,
+    city_info: &models::CityInfo,
+    weather: &models::WeatherData,
+    counts: &mut AlertCounts,
+) -> Result<(), AppError> {
+
+    // Check users in this city for alerts
+    let users = db.get_users_by_city(&city_info.city).await?;
+    // Weather descriptions per locale, fetched only when an alert needs one
+    let mut descriptions = HashMap::from([(
+        i18n::DEFAULT_LOCALE.to_string(),
+        weather.description.clone(),
+    )]);
+
+    for user in users {
+        if db.is_suppressed(&user.email).await? {
+            continue;
+        }
+        let Some(prefs) = db.get_user_preferences(user.id).await? else {
+            continue;
+        };
+        let Some(condition) = check_alert_conditions(weather, &prefs) else {
+            continue;
+        };
+
+        let description = if condition.mentions_conditions() {
+            localized_description(weather_client, city_info, &user.locale, &mut descriptions).await
+        } else {
+            String::new()
+        };
+        let alert_message = condition.message(&user.locale, &description);
+
+        // Over the daily cap: keep the alert for the next digest instead
+        let cap = config.alert_max_per_user_per_day;
+        if cap > 0 && db.count_recent_alerts(user.id, 24).await? >= cap {
+            let reason = format!("Daily limit of {} alerts reached", cap);
+            info!("⏸️ Deferring alert for {}: {}", user.email, reason);
+            db.log_deferred_alert(
+                user.id,
+                condition.alert_type(),
+                &alert_message,
+                "email",
+                &reason,
+            )
+            .await?;
+            counts.deferred += 1;
+            continue;
+        }
+
+        info!("🔔 Queueing alert for {}: {}", user.email, alert_message);
+
+        let notification = models::Notification::WeatherAlert {
+            city: city_info.city.clone(),
+            alert_type: condition.alert_type().to_string(),
+            message: alert_message.clone(),
+            locale: user.locale.clone(),
+        };
+        let (alert_log, message) = db
+            .log_alert(
+                &user,
+                condition.alert_type(),
+                &alert_message,
+                "email",
+                &notification,
+                config.outbox_max_attempts,
+            )
+            .await?;
+        info!("📥 Queued {} notification {} for {}", message.kind, message.id, user.email);
+        // Only announced once it is certain to be sent
+        events.publish(events::LiveEvent::Alert(events::AlertEvent {
+            city: city_info.city.clone(),
+            alert: alert_log,
+        }));
+        counts.queued += 1;
+    }
+
     Ok(())
 }
 
+/// Returns the weather description in `locale`, asking the provider for a translation the
+/// first time a locale is needed for a city. Falls back to English if that request fails.
+async fn localized_description(
+    weather_client: &weather::WeatherClient,
+    city_info: &models::CityInfo,
+    locale: &str,
+    descriptions: &mut HashMap<String, String>,
+) -> String {
+    if let Some(description) = descriptions.get(locale) {
+        return description.clone();
+    }
+
+    let description = match weather_client
+        .get_weather(&city_info.city, &city_info.country, Some(locale))
+        .await
+    {
+        Ok(localized) => localized.description,
+        Err(e) => {
+            log::warn!(
+                "⚠️ Could not fetch '{}' description for {}: {}",
+                locale, city_info.city, e
+            );
+            descriptions[i18n::DEFAULT_LOCALE].clone()
+        }
+    };
+    descriptions.insert(locale.to_string(), description.clone());
+    description
+}
+
 fn check_alert_conditions(
     weather: &models::WeatherData,
     prefs: &models::UserPreferences,
-) -> Option<String> {
+) -> Option<models::AlertCondition> {
+    use models::AlertCondition;
+
     let temp = weather.temperature;
     let conditions = weather.conditions.to_lowercase();
 
     if let Some(max_temp) = prefs.max_temp {
         if temp > max_temp as f64 {
-            return Some(format!(
-                "🌡️ High temperature alert! Current: {:.1}°C (Your limit: {}°C)",
-                temp, max_temp
-            ));
+            return Some(AlertCondition::HighTemperature {
+                current: temp,
+                limit: max_temp,
+            });
         }
     }
 
     if let Some(min_temp) = prefs.min_temp {
         if temp < min_temp as f64 {
-            return Some(format!(
-                "🥶 Low temperature alert! Current: {:.1}°C (Your limit: {}°C)",
-                temp, min_temp
-            ));
+            return Some(AlertCondition::LowTemperature {
+                current: temp,
+                limit: min_temp,
+            });
         }
     }
 
     if prefs.alert_on_rain && conditions.contains("rain") {
-        return Some(format!("☔ Rain alert! Current conditions: {}", weather.conditions));
+        return Some(AlertCondition::Rain);
     }
 
     if prefs.alert_on_snow && conditions.contains("snow") {
-        return Some(format!("❄️ Snow alert! Current conditions: {}", weather.conditions));
+        return Some(AlertCondition::Snow);
     }
 
     if prefs.alert_on_storm && (conditions.contains("storm") || conditions.contains("thunder")) {
-        return Some(format!("⚡ Storm alert! Current conditions: {}", weather.conditions));
+        return Some(AlertCondition::Storm);
     }
 
     None
@@ -287,8 +706,14 @@ fn check_alert_conditions(
 fn list_jobs() {
     println!("📋 Scheduled CRON Jobs:");
     println!("  ⏰ Weather Fetch: Every 2 hours (0 0 */2 * * *)");
+    println!("  🧹 Unverified User Cleanup: Every hour (0 0 * * * *)");
+    println!("  🗞️ Alert Digest: Daily at 18:00 UTC (0 0 18 * * *)");
     println!("\n🔧 Manual Commands:");
     println!("  cargo run -- fetch-weather    (Manually fetch weather now)");
     println!("  cargo run -- init-db          (Initialize database)");
+    println!("  cargo run -- send-digests     (Send deferred alert digests now)");
     println!("  cargo run -- test-email       (Send test email)");
+    println!("  cargo run -- preview          (Render a notification without sending it)");
+    println!("  cargo run -- export           (Export weather or alert data as CSV, NDJSON or Parquet)");
+    println!("  cargo run -- set-role         (Grant or revoke the admin role)");
 }
\ No newline at end of file
@@ -1,18 +1,27 @@
+use async_graphql::{InputObject, SimpleObject};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use utoipa::ToSchema;
 use validator::Validate;
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema, SimpleObject)]
+#[graphql(complex)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub city: String,
     pub country: String,
     pub created_at: DateTime<Utc>,
+    pub locale: String,
+    /// `pending` users have not confirmed their email yet, `active` users receive alerts,
+    /// `paused` users have unsubscribed
+    pub status: String,
+    /// `user` or `admin`
+    pub role: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CreateUserRequest {
     #[validate(email(message = "Invalid email format"))]
     pub email: String,
@@ -20,9 +29,107 @@ pub struct CreateUserRequest {
     pub city: String,
     #[validate(length(min = 2, max = 2, message = "Country code must be 2 letters (e.g., US, IN)"))]
     pub country: String,
+    /// Language for notifications, e.g. `en`, `hi` or `de` (defaults to `en`)
+    pub locale: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+/// Partial update of a user; fields left out are unchanged
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct UpdateUserRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: Option<String>,
+    #[validate(length(min = 2, message = "City name too short"))]
+    pub city: Option<String>,
+    #[validate(length(min = 2, max = 2, message = "Country code must be 2 letters (e.g., US, IN)"))]
+    pub country: Option<String>,
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct LoginRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+/// A bearer token issued after a magic-link login. Only a hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// One administrative action, written for every call to an admin-only endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AdminAuditEntry {
+    pub id: Uuid,
+    /// `user:<id>` for admin users, `api_key:<fingerprint>` for admin API keys
+    pub actor: String,
+    pub action: String,
+    pub target: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A client-chosen `Idempotency-Key` and, once the request completed, the response
+/// replayed for repeats of it
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct IdempotencyKey {
+    /// HTTP method, path and credentials of the request, so keys of different clients
+    /// and routes never collide
+    pub scope: String,
+    pub key: String,
+    /// SHA-256 of the request body
+    pub request_hash: String,
+    /// `in_progress` or `completed`
+    pub status: String,
+    pub status_code: Option<i32>,
+    pub response_headers: Option<serde_json::Value>,
+    pub response_body: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// `JobRun::kind` of the scheduled, CLI and API weather fetches
+pub const JOB_WEATHER_FETCH: &str = "weather_fetch";
+
+/// One run of a background job such as the weather fetch, with its progress
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct JobRun {
+    pub id: Uuid,
+    /// `weather_fetch`
+    pub kind: String,
+    /// What started the run: `cron`, `cli` or `api`
+    pub source: String,
+    /// Admin who started an `api` run
+    pub actor: Option<String>,
+    /// `running`, `succeeded` or `failed`
+    pub status: String,
+    pub cities_total: i32,
+    pub cities_succeeded: i32,
+    pub cities_failed: i32,
+    /// Alerts queued for delivery
+    pub alerts_queued: i32,
+    /// Alerts held back for the digest by the daily cap
+    pub alerts_deferred: i32,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Returned once when a token is issued; the plain token cannot be retrieved again
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IssuedToken {
+    pub token: String,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema, SimpleObject)]
 pub struct UserPreferences {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -35,7 +142,8 @@ pub struct UserPreferences {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema, InputObject)]
+#[graphql(name = "PreferencesInput")]
 pub struct UpdatePreferencesRequest {
     pub min_temp: Option<i32>,
     pub max_temp: Option<i32>,
@@ -44,7 +152,7 @@ pub struct UpdatePreferencesRequest {
     pub alert_on_storm: Option<bool>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema, SimpleObject)]
 pub struct WeatherData {
     pub id: Uuid,
     pub city: String,
@@ -59,13 +167,407 @@ pub struct WeatherData {
     pub fetched_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+/// Bucket size for aggregated weather history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryBucket {
+    Hour,
+    Day,
+    Week,
+}
+
+impl HistoryBucket {
+    /// Field name for Postgres `date_trunc`; weeks start on Monday
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryBucket::Hour => "hour",
+            HistoryBucket::Day => "day",
+            HistoryBucket::Week => "week",
+        }
+    }
+
+    /// Time range covered when the request gives no `from`
+    pub fn default_range(&self) -> chrono::Duration {
+        match self {
+            HistoryBucket::Hour => chrono::Duration::hours(24),
+            HistoryBucket::Day => chrono::Duration::days(30),
+            HistoryBucket::Week => chrono::Duration::weeks(26),
+        }
+    }
+
+    /// Longest range one request may aggregate, so the query and the response stay bounded
+    pub fn max_range(&self) -> chrono::Duration {
+        match self {
+            HistoryBucket::Hour => chrono::Duration::days(31),
+            HistoryBucket::Day => chrono::Duration::days(366),
+            HistoryBucket::Week => chrono::Duration::weeks(260),
+        }
+    }
+
+    pub fn check_range(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<(), crate::error::AppError> {
+        if to - from > self.max_range() {
+            return Err(crate::error::AppError::Validation(format!(
+                "Ranges aggregated by {} may span at most {} days",
+                self.as_str(),
+                self.max_range().num_days()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Weather readings of one city aggregated over one time bucket
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WeatherBucket {
+    /// Start of the bucket (UTC)
+    pub bucket_start: DateTime<Utc>,
+    pub readings: i64,
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    pub avg_temperature: f64,
+    pub min_humidity: i32,
+    pub max_humidity: i32,
+    pub avg_humidity: f64,
+    pub min_wind_speed: f64,
+    pub max_wind_speed: f64,
+    pub avg_wind_speed: f64,
+    /// Most common condition in the bucket
+    pub conditions: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema, SimpleObject)]
 pub struct AlertLog {
     pub id: Uuid,
     pub user_id: Uuid,
     pub alert_type: String,
     pub message: String,
     pub sent_at: DateTime<Utc>,
+    pub channel: String,
+    /// One of `queued`, `sent`, `failed`, `bounced`, `deferred` or `digested`
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub provider_message_id: Option<String>,
+    pub updated_at: DateTime<Utc>,
+    pub status_reason: Option<String>,
+}
+
+/// A notification waiting in (or already delivered from) the outbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    WeatherAlert {
+        city: String,
+        alert_type: String,
+        message: String,
+        #[serde(default = "default_locale")]
+        locale: String,
+    },
+    Welcome {
+        city: String,
+        #[serde(default = "default_locale")]
+        locale: String,
+    },
+    Verification {
+        city: String,
+        locale: String,
+    },
+    LoginLink {
+        #[serde(default = "default_locale")]
+        locale: String,
+    },
+    /// Alerts that were held back by the daily cap, sent as one email
+    Digest {
+        city: String,
+        alerts: Vec<DigestEntry>,
+        #[serde(default = "default_locale")]
+        locale: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestEntry {
+    pub alert_type: String,
+    pub message: String,
+    pub logged_at: DateTime<Utc>,
+}
+
+impl From<AlertLog> for DigestEntry {
+    fn from(alert: AlertLog) -> Self {
+        DigestEntry {
+            alert_type: alert.alert_type,
+            message: alert.message,
+            logged_at: alert.sent_at,
+        }
+    }
+}
+
+fn default_locale() -> String {
+    crate::i18n::DEFAULT_LOCALE.to_string()
+}
+
+impl Notification {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::WeatherAlert { .. } => "weather_alert",
+            Notification::Welcome { .. } => "welcome",
+            Notification::Verification { .. } => "verification",
+            Notification::Digest { .. } => "digest",
+            Notification::LoginLink { .. } => "login_link",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub kind: String,
+    pub recipient: String,
+    #[schema(value_type = Object)]
+    pub payload: sqlx::types::Json<Notification>,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub alert_log_id: Option<Uuid>,
+}
+
+/// A weather condition that matched a user's preferences
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertCondition {
+    HighTemperature { current: f64, limit: i32 },
+    LowTemperature { current: f64, limit: i32 },
+    Rain,
+    Snow,
+    Storm,
+}
+
+impl AlertCondition {
+    pub fn kind(&self) -> AlertKind {
+        match self {
+            AlertCondition::HighTemperature { .. } => AlertKind::HighTemperature,
+            AlertCondition::LowTemperature { .. } => AlertKind::LowTemperature,
+            AlertCondition::Rain => AlertKind::Rain,
+            AlertCondition::Snow => AlertKind::Snow,
+            AlertCondition::Storm => AlertKind::Storm,
+        }
+    }
+
+    pub fn alert_type(&self) -> &'static str {
+        self.kind().as_str()
+    }
+
+    /// Whether the alert message quotes the weather description, which then needs to be
+    /// in the recipient's language
+    pub fn mentions_conditions(&self) -> bool {
+        matches!(self, AlertCondition::Rain | AlertCondition::Snow | AlertCondition::Storm)
+    }
+
+    /// The alert text in `locale`; `conditions` is the (localized) weather description
+    pub fn message(&self, locale: &str, conditions: &str) -> String {
+        use crate::i18n::t;
+
+        match self {
+            AlertCondition::HighTemperature { current, limit } => t(
+                locale,
+                "alert.high_temp",
+                &[("current", &format!("{:.1}", current)), ("limit", &limit.to_string())],
+            ),
+            AlertCondition::LowTemperature { current, limit } => t(
+                locale,
+                "alert.low_temp",
+                &[("current", &format!("{:.1}", current)), ("limit", &limit.to_string())],
+            ),
+            AlertCondition::Rain => t(locale, "alert.rain", &[("conditions", conditions)]),
+            AlertCondition::Snow => t(locale, "alert.snow", &[("conditions", conditions)]),
+            AlertCondition::Storm => t(locale, "alert.storm", &[("conditions", conditions)]),
+        }
+    }
+}
+
+/// What an alert is about, one per `AlertCondition` variant; stored as `alert_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    HighTemperature,
+    LowTemperature,
+    Rain,
+    Snow,
+    Storm,
+}
+
+impl AlertKind {
+    pub const ALL: [AlertKind; 5] = [
+        AlertKind::HighTemperature,
+        AlertKind::LowTemperature,
+        AlertKind::Rain,
+        AlertKind::Snow,
+        AlertKind::Storm,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::HighTemperature => "high_temperature",
+            AlertKind::LowTemperature => "low_temperature",
+            AlertKind::Rain => "rain",
+            AlertKind::Snow => "snow",
+            AlertKind::Storm => "storm",
+        }
+    }
+
+    /// Storms are `severe`, crossed temperature thresholds `moderate`, rain and snow `minor`
+    pub fn severity(&self) -> AlertSeverity {
+        match self {
+            AlertKind::Storm => AlertSeverity::Severe,
+            AlertKind::HighTemperature | AlertKind::LowTemperature => AlertSeverity::Moderate,
+            AlertKind::Rain | AlertKind::Snow => AlertSeverity::Minor,
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.as_str() == kind)
+    }
+}
+
+/// How serious an alert is, derived from its `AlertKind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertSeverity {
+    Minor,
+    Moderate,
+    Severe,
+}
+
+impl AlertSeverity {
+    const ALL: [AlertSeverity; 3] = [AlertSeverity::Minor, AlertSeverity::Moderate, AlertSeverity::Severe];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertSeverity::Minor => "minor",
+            AlertSeverity::Moderate => "moderate",
+            AlertSeverity::Severe => "severe",
+        }
+    }
+
+    fn parse(severity: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == severity)
+    }
+}
+
+/// Filters for user listings
+#[derive(Debug, Default)]
+pub struct UserFilter {
+    pub city: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Filters for data exports. Times filter on `fetched_at` (weather) or `sent_at` (alerts);
+/// city and country of alerts are those of the user.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportFilter {
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// An alert log row as exported, with the user's location instead of their address
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AlertExportRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub city: String,
+    pub country: String,
+    pub alert_type: String,
+    pub message: String,
+    pub channel: String,
+    pub status: String,
+    pub attempts: i32,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Filters for alert listings. `alert_types` of `None` means any type.
+#[derive(Debug, Default)]
+pub struct AlertFilter {
+    pub alert_types: Option<Vec<String>>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl AlertFilter {
+    /// Narrows the alert types to `kind` and/or those of `severity`. Unknown values are
+    /// rejected rather than silently matching nothing.
+    pub fn new(
+        kind: Option<&str>,
+        severity: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Self, crate::error::AppError> {
+        use crate::error::AppError;
+
+        let kind = kind
+            .map(|kind| {
+                AlertKind::parse(kind)
+                    .ok_or_else(|| AppError::Validation(format!("Unknown alert kind: {}", kind)))
+            })
+            .transpose()?;
+        let severity = severity
+            .map(|severity| {
+                AlertSeverity::parse(severity)
+                    .ok_or_else(|| AppError::Validation(format!("Unknown severity: {}", severity)))
+            })
+            .transpose()?;
+
+        let alert_types = (kind.is_some() || severity.is_some()).then(|| {
+            AlertKind::ALL
+                .into_iter()
+                .filter(|k| kind.is_none_or(|kind| kind == *k))
+                .filter(|k| severity.is_none_or(|severity| k.severity() == severity))
+                .map(|k| k.as_str().to_string())
+                .collect()
+        });
+
+        Ok(AlertFilter {
+            alert_types,
+            from,
+            to,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Suppression {
+    pub email: String,
+    /// `hard_bounce`, `complaint` or `smtp_failures`
+    pub reason: String,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Event posted by the mail provider's bounce/complaint webhook
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MailEvent {
+    pub event: MailEventKind,
+    pub email: String,
+    /// For bounces: `permanent` (hard) or `transient` (soft)
+    pub bounce_type: Option<String>,
+    /// Message-ID of the email the event refers to, if the provider reports it
+    pub message_id: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MailEventKind {
+    Bounce,
+    Complaint,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,17 +584,34 @@ pub struct WeatherResponse {
     pub fetched_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UserWithPreferences {
     pub user: User,
     pub preferences: Option<UserPreferences>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Everything stored about a user, for data access requests (GDPR Art. 15)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserDataExport {
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    pub preferences: Option<UserPreferences>,
+    /// Full alert history, oldest first
+    pub alerts: Vec<AlertLog>,
+    /// Active sign-in tokens (their hashes are not included)
+    pub api_tokens: Vec<ApiToken>,
+    /// Set if mail to the address is blocked after bounces or complaints
+    pub suppression: Option<Suppression>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub message: String,
+    /// Cursor of the next page of a paginated list; absent on the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
@@ -101,14 +620,18 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             message: message.to_string(),
+            next_cursor: None,
         }
     }
+}
 
-    pub fn error(message: &str) -> Self {
+impl<T> ApiResponse<Vec<T>> {
+    pub fn page(page: crate::pagination::Page<T>, message: &str) -> Self {
         Self {
-            success: false,
-            data: None,
+            success: true,
+            data: Some(page.items),
             message: message.to_string(),
+            next_cursor: page.next_cursor,
         }
     }
 }
@@ -151,4 +674,75 @@ pub struct Sys {
 pub struct CityInfo {
     pub city: String,
     pub country: String,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_ranges_are_capped() {
+        let to = Utc::now();
+
+        assert!(HistoryBucket::Hour.check_range(to - chrono::Duration::days(31), to).is_ok());
+        assert!(HistoryBucket::Hour.check_range(to - chrono::Duration::days(32), to).is_err());
+        assert!(HistoryBucket::Day.check_range(to - chrono::Duration::days(366), to).is_ok());
+        assert!(HistoryBucket::Week
+            .check_range(DateTime::<Utc>::UNIX_EPOCH, to)
+            .is_err());
+
+        // Every default range is allowed
+        for bucket in [HistoryBucket::Hour, HistoryBucket::Day, HistoryBucket::Week] {
+            assert!(bucket.check_range(to - bucket.default_range(), to).is_ok());
+        }
+    }
+
+    fn types(filter: AlertFilter) -> Option<Vec<String>> {
+        filter.alert_types
+    }
+
+    #[test]
+    fn severity_filter_follows_alert_kinds() {
+        let severe = AlertFilter::new(None, Some("severe"), None, None).unwrap();
+        assert_eq!(types(severe), Some(vec!["storm".to_string()]));
+
+        let moderate = AlertFilter::new(None, Some("moderate"), None, None).unwrap();
+        assert_eq!(
+            types(moderate),
+            Some(vec!["high_temperature".to_string(), "low_temperature".to_string()])
+        );
+
+        // Every kind has exactly one severity
+        for kind in AlertKind::ALL {
+            let matching = AlertSeverity::ALL
+                .into_iter()
+                .filter(|severity| kind.severity() == *severity)
+                .count();
+            assert_eq!(matching, 1);
+        }
+    }
+
+    #[test]
+    fn kind_and_severity_combine() {
+        let filter = AlertFilter::new(Some("rain"), Some("minor"), None, None).unwrap();
+        assert_eq!(types(filter), Some(vec!["rain".to_string()]));
+
+        let filter = AlertFilter::new(Some("rain"), Some("severe"), None, None).unwrap();
+        assert_eq!(types(filter), Some(vec![]));
+
+        assert_eq!(types(AlertFilter::new(None, None, None, None).unwrap()), None);
+    }
+
+    #[test]
+    fn unknown_filter_values_are_rejected() {
+        assert!(AlertFilter::new(Some("hail"), None, None, None).is_err());
+        assert!(AlertFilter::new(None, Some("extreme"), None, None).is_err());
+    }
+
+    #[test]
+    fn condition_kinds_match_stored_alert_types() {
+        let high = AlertCondition::HighTemperature { current: 31.0, limit: 30 };
+        assert_eq!(high.alert_type(), "high_temperature");
+        assert_eq!(high.kind().severity(), AlertSeverity::Moderate);
+        assert_eq!(AlertCondition::Storm.kind().severity(), AlertSeverity::Severe);
+    }
+}
@@ -0,0 +1,364 @@
+use crate::chart;
+use crate::config::Config;
+use crate::db::Database;
+use crate::email::EmailClient;
+use crate::error::AppError;
+use crate::models::{Notification, OutboxMessage};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+
+// How long a claimed message stays invisible to other workers while it is being sent
+const LEASE_SECS: f64 = 300.0;
+// Upper bound for the exponential backoff between attempts
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+// Window of the global send rate (`EMAIL_MAX_PER_MINUTE`)
+const RATE_WINDOW_SECS: f64 = 60.0;
+
+/// Writes a notification to the outbox. Delivery happens later in the outbox worker.
+pub async fn enqueue(
+    db: &Database,
+    config: &Config,
+    user_id: Option<uuid::Uuid>,
+    alert_log_id: Option<uuid::Uuid>,
+    recipient: &str,
+    notification: &Notification,
+) -> Result<(), AppError> {
+    let message = db
+        .enqueue_notification(
+            user_id,
+            alert_log_id,
+            recipient,
+            notification,
+            config.outbox_max_attempts,
+        )
+        .await?;
+    info!("📥 Queued {} notification {} for {}", message.kind, message.id, recipient);
+    Ok(())
+}
+
+/// Polls the outbox forever, delivering due messages.
+pub async fn run_worker(db: Database, email_client: EmailClient, config: Config) {
+    info!(
+        "📮 Outbox worker started (batch size {}, poll every {}s)",
+        config.outbox_batch_size, config.outbox_poll_interval_secs
+    );
+
+    let idle = tokio::time::Duration::from_secs(config.outbox_poll_interval_secs.max(1));
+
+    loop {
+        match process_batch(&db, &email_client, &config).await {
+            // A full batch likely means more work is waiting, so go again straight away
+            Ok(Batch::Claimed(n)) if n as i64 >= config.outbox_batch_size => continue,
+            Ok(_) => {}
+            Err(e) => error!("❌ Outbox worker error: {}", e),
+        }
+        tokio::time::sleep(idle).await;
+    }
+}
+
+/// Delivers everything that is currently due, then returns. Used by the CLI, which has
+/// no long-running worker, so it waits out the send rate rather than leave messages
+/// pending.
+pub async fn drain(db: &Database, email_client: &EmailClient, config: &Config) -> Result<usize, AppError> {
+    let mut total = 0;
+    loop {
+        match process_batch(db, email_client, config).await? {
+            Batch::Claimed(0) => return Ok(total),
+            Batch::Claimed(n) => total += n,
+            Batch::RateLimited(wait) => {
+                info!("⏳ Send rate reached, waiting {}s", wait.num_seconds());
+                tokio::time::sleep(wait.to_std().unwrap_or_default()).await;
+            }
+        }
+    }
+}
+
+/// Outcome of one pass over the outbox
+#[derive(Debug, PartialEq, Eq)]
+pub enum Batch {
+    /// Number of messages claimed; zero when nothing is due
+    Claimed(usize),
+    /// The send rate is used up while messages are due. Holds the time until the next
+    /// message may be sent.
+    RateLimited(Duration),
+}
+
+/// Claims one batch of due messages and attempts to deliver each of them.
+pub async fn process_batch(
+    db: &Database,
+    email_client: &EmailClient,
+    config: &Config,
+) -> Result<Batch, AppError> {
+    // With a global send rate, only claim what still fits into the last minute's budget;
+    // the rest stays pending until a later pass
+    let limit = if config.email_max_per_minute > 0 {
+        let sent = db.count_sent_since(RATE_WINDOW_SECS).await?;
+        (config.email_max_per_minute - sent).clamp(0, config.outbox_batch_size)
+    } else {
+        config.outbox_batch_size
+    };
+    if limit == 0 {
+        if !db.has_due_outbox_messages().await? {
+            return Ok(Batch::Claimed(0));
+        }
+        let oldest = db.oldest_sent_since(RATE_WINDOW_SECS).await?;
+        return Ok(Batch::RateLimited(rate_limit_wait(oldest, Utc::now())));
+    }
+
+    let batch = db.claim_outbox_batch(limit, LEASE_SECS).await?;
+    if batch.is_empty() {
+        return Ok(Batch::Claimed(0));
+    }
+
+    info!("📤 Delivering {} outbox message(s)", batch.len());
+
+    let deliveries = batch.iter().map(|message| async move {
+        // Suppressed addresses are checked at send time, so a suppression added after a
+        // message was queued still applies
+        let result = match db.is_suppressed(&message.recipient).await {
+            Ok(true) => Ok(None),
+            Ok(false) => deliver(db, email_client, message).await.map(Some),
+            Err(e) => Err(e),
+        };
+        (message, result)
+    });
+
+    // Each message is recorded on its own: a database error is logged and the rest of the
+    // batch is still recorded, instead of leaving delivered messages to be sent again once
+    // their lease runs out
+    for (message, result) in futures::future::join_all(deliveries).await {
+        let attempt = message.attempts + 1;
+        match result {
+            Ok(Some(provider_message_id)) => {
+                if let Err(e) = db.mark_outbox_sent(message.id).await {
+                    error!("❌ Could not mark outbox message {} as sent: {}", message.id, e);
+                    continue;
+                }
+                info!("✅ {} notification delivered to {}", message.kind, message.recipient);
+                log_error(
+                    message,
+                    "reset failure count",
+                    db.reset_email_failures(&message.recipient).await,
+                );
+                if let Some(alert_id) = message.alert_log_id {
+                    log_error(
+                        message,
+                        "update alert",
+                        db.update_alert_delivery(alert_id, "sent", attempt, None, Some(&provider_message_id))
+                            .await,
+                    );
+                }
+            }
+            Ok(None) => {
+                info!("🚫 Skipping {} notification to suppressed address {}", message.kind, message.recipient);
+                let error = "Recipient address is on the suppression list";
+                if let Err(e) = db.mark_outbox_suppressed(message.id, error).await {
+                    error!("❌ Could not mark outbox message {} as suppressed: {}", message.id, e);
+                    continue;
+                }
+                if let Some(alert_id) = message.alert_log_id {
+                    log_error(
+                        message,
+                        "update alert",
+                        db.update_alert_delivery(alert_id, "failed", message.attempts, Some(error), None)
+                            .await,
+                    );
+                }
+            }
+            Err(AppError::EmailRejected(reason)) => {
+                // Retrying a permanent rejection cannot succeed; dead-letter right away
+                error!("☠️ {} rejected permanently: {}", message.recipient, reason);
+                let error = format!("Permanently rejected: {}", reason);
+                if let Err(e) = db.mark_outbox_failed(message.id, &error, None).await {
+                    error!("❌ Could not mark outbox message {} as failed: {}", message.id, e);
+                    continue;
+                }
+                if let Some(alert_id) = message.alert_log_id {
+                    log_error(
+                        message,
+                        "update alert",
+                        db.update_alert_delivery(alert_id, "failed", attempt, Some(&error), None)
+                            .await,
+                    );
+                }
+
+                match db.record_permanent_failure(&message.recipient, &reason).await {
+                    Ok(failures) if failures >= config.suppress_after_failures => log_error(
+                        message,
+                        "suppress recipient",
+                        db.suppress_email(
+                            &message.recipient,
+                            "smtp_failures",
+                            Some(&format!("{} permanent SMTP failures, last: {}", failures, reason)),
+                        )
+                        .await,
+                    ),
+                    Ok(_) => {}
+                    Err(e) => log_error(message, "record permanent failure", Err(e)),
+                }
+            }
+            Err(e) => {
+                let error = e.to_string();
+                if attempt >= message.max_attempts {
+                    error!(
+                        "☠️ Outbox message {} to {} dead-lettered after {} attempts: {}",
+                        message.id, message.recipient, attempt, e
+                    );
+                    if let Err(e) = db.mark_outbox_failed(message.id, &error, None).await {
+                        error!("❌ Could not mark outbox message {} as failed: {}", message.id, e);
+                        continue;
+                    }
+                    if let Some(alert_id) = message.alert_log_id {
+                        log_error(
+                            message,
+                            "update alert",
+                            db.update_alert_delivery(alert_id, "failed", attempt, Some(&error), None)
+                                .await,
+                        );
+                    }
+                } else {
+                    let retry_at = Utc::now() + retry_delay(config.outbox_retry_base_secs, attempt);
+                    warn!(
+                        "🔁 Outbox message {} to {} failed (attempt {}/{}), retrying at {}: {}",
+                        message.id, message.recipient, attempt, message.max_attempts, retry_at, e
+                    );
+                    if let Err(e) = db.mark_outbox_failed(message.id, &error, Some(retry_at)).await {
+                        error!("❌ Could not reschedule outbox message {}: {}", message.id, e);
+                        continue;
+                    }
+                    if let Some(alert_id) = message.alert_log_id {
+                        log_error(
+                            message,
+                            "update alert",
+                            db.update_alert_delivery(alert_id, "queued", attempt, Some(&error), None)
+                                .await,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Batch::Claimed(batch.len()))
+}
+
+async fn deliver(
+    db: &Database,
+    email_client: &EmailClient,
+    message: &OutboxMessage,
+) -> Result<String, AppError> {
+    match &message.payload.0 {
+        Notification::WeatherAlert {
+            city,
+            message: text,
+            locale,
+            ..
+        } => {
+            let chart = chart::alert_chart(db, city, message.user_id).await;
+            email_client
+                .send_weather_alert(
+                    &message.recipient,
+                    message.user_id,
+                    city,
+                    text,
+                    locale,
+                    chart,
+                )
+                .await
+        }
+        Notification::Welcome { city, locale } => {
+            email_client
+                .send_welcome_email(&message.recipient, message.user_id, city, locale)
+                .await
+        }
+        Notification::Verification { city, locale } => {
+            let user_id = message.user_id.ok_or_else(|| {
+                AppError::Internal("Verification message has no user".to_string())
+            })?;
+            email_client
+                .send_verification_email(&message.recipient, user_id, city, locale)
+                .await
+        }
+        Notification::LoginLink { locale } => {
+            let user_id = message.user_id.ok_or_else(|| {
+                AppError::Internal("Login link message has no user".to_string())
+            })?;
+            email_client
+                .send_login_link(&message.recipient, user_id, locale)
+                .await
+        }
+        Notification::Digest { city, alerts, locale } => {
+            email_client
+                .send_digest(&message.recipient, message.user_id, city, alerts, locale)
+                .await
+        }
+    }
+}
+
+/// Logs a failed follow-up update of a message whose outcome is already recorded
+fn log_error(message: &OutboxMessage, action: &str, result: Result<(), AppError>) {
+    if let Err(e) = result {
+        error!("❌ Could not {} for outbox message {}: {}", action, message.id, e);
+    }
+}
+
+/// Time until the oldest send of the rate window drops out of it, and with it a slot
+/// frees up. At least a second, so a caller never spins.
+fn rate_limit_wait(oldest_sent: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Duration {
+    let window = Duration::milliseconds((RATE_WINDOW_SECS * 1000.0) as i64);
+    oldest_sent
+        .map_or(Duration::zero(), |sent| sent + window - now)
+        .max(Duration::seconds(1))
+}
+
+/// Exponential backoff: base, 2x base, 4x base, ... capped at `MAX_RETRY_DELAY_SECS`.
+fn retry_delay(base_secs: i64, attempt: i32) -> Duration {
+    let exponent = (attempt - 1).clamp(0, 20) as u32;
+    let secs = base_secs
+        .max(1)
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(MAX_RETRY_DELAY_SECS);
+    Duration::seconds(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_each_attempt() {
+        assert_eq!(retry_delay(60, 1), Duration::seconds(60));
+        assert_eq!(retry_delay(60, 2), Duration::seconds(120));
+        assert_eq!(retry_delay(60, 3), Duration::seconds(240));
+        assert_eq!(retry_delay(60, 5), Duration::seconds(960));
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(60, 10), Duration::seconds(MAX_RETRY_DELAY_SECS));
+        assert_eq!(retry_delay(60, 1000), Duration::seconds(MAX_RETRY_DELAY_SECS));
+        assert_eq!(retry_delay(i64::MAX, 3), Duration::seconds(MAX_RETRY_DELAY_SECS));
+    }
+
+    #[test]
+    fn retry_delay_handles_odd_input() {
+        // A base below one second still backs off
+        assert_eq!(retry_delay(0, 1), Duration::seconds(1));
+        assert_eq!(retry_delay(-5, 2), Duration::seconds(2));
+        // Attempts start at 1; anything lower gets the base delay
+        assert_eq!(retry_delay(30, 0), Duration::seconds(30));
+    }
+
+    #[test]
+    fn rate_limit_wait_lasts_until_the_oldest_send_leaves_the_window() {
+        let now = Utc::now();
+
+        assert_eq!(
+            rate_limit_wait(Some(now - Duration::seconds(45)), now),
+            Duration::seconds(15)
+        );
+        // Never shorter than a second, also when the window has just moved on
+        assert_eq!(rate_limit_wait(Some(now - Duration::seconds(60)), now), Duration::seconds(1));
+        assert_eq!(rate_limit_wait(None, now), Duration::seconds(1));
+    }
+}
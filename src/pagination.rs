@@ -0,0 +1,152 @@
+use crate::error::AppError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Page size used when a request asks for more
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// Position in a list ordered newest first by `(timestamp, id)`. The next page starts
+/// right after the row the cursor points at, so rows inserted meanwhile don't shift it.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// Opaque form handed to clients as `next_cursor`
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.at.timestamp_micros(), self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::Validation("Invalid cursor".to_string());
+
+        let raw = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (micros, id) = raw.split_once(':').ok_or_else(invalid)?;
+        let at = micros
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or_else(invalid)?;
+        let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+
+        Ok(Cursor { at, id })
+    }
+
+    /// Decodes an optional `cursor` query parameter
+    pub fn parse(cursor: Option<&str>) -> Result<Option<Self>, AppError> {
+        cursor.map(Cursor::decode).transpose()
+    }
+}
+
+/// Clamps a requested page size to `1..=MAX_PAGE_SIZE`
+pub fn page_size(requested: Option<i64>, default: i64) -> i64 {
+    requested.unwrap_or(default).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// One page of a list, with the cursor of the following page if there is one
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with `LIMIT limit + 1`: the extra row only tells
+    /// that another page exists and is dropped.
+    pub fn from_rows(mut rows: Vec<T>, limit: i64, key: impl Fn(&T) -> Cursor) -> Self {
+        let has_more = rows.len() as i64 > limit;
+        rows.truncate(limit.max(0) as usize);
+        let next_cursor = if has_more {
+            rows.last().map(|row| key(row).encode())
+        } else {
+            None
+        };
+
+        Page {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(micros: i64) -> Cursor {
+        Cursor {
+            at: DateTime::from_timestamp_micros(micros).unwrap(),
+            id: Uuid::from_u128(micros as u128),
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let original = cursor(1_704_067_200_123_456);
+        let decoded = Cursor::decode(&original.encode()).unwrap();
+
+        assert_eq!(decoded.at, original.at);
+        assert_eq!(decoded.id, original.id);
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        let encode = |raw: &str| URL_SAFE_NO_PAD.encode(raw);
+        let invalid = [
+            "not base64!".to_string(),
+            encode("no separator"),
+            encode("soon:6b39c9c6-7f1c-4a3e-9d7e-0f1a2b3c4d5e"),
+            encode("1704067200000000:not-a-uuid"),
+            URL_SAFE_NO_PAD.encode([0xff, 0xfe, b':']),
+        ];
+
+        for cursor in invalid {
+            assert!(
+                matches!(Cursor::decode(&cursor), Err(AppError::Validation(_))),
+                "{} should be rejected",
+                cursor
+            );
+        }
+    }
+
+    #[test]
+    fn parse_passes_through_a_missing_cursor() {
+        assert!(Cursor::parse(None).unwrap().is_none());
+        assert!(Cursor::parse(Some("garbage")).is_err());
+    }
+
+    #[test]
+    fn page_size_is_clamped() {
+        assert_eq!(page_size(None, 100), 100);
+        assert_eq!(page_size(Some(0), 100), 1);
+        assert_eq!(page_size(Some(-5), 100), 1);
+        assert_eq!(page_size(Some(10_000), 100), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn page_with_an_extra_row_has_more() {
+        let rows: Vec<i64> = (1..=4).collect();
+        let page = Page::from_rows(rows, 3, |row| cursor(*row));
+
+        assert_eq!(page.items, vec![1, 2, 3]);
+        // Points at the last row returned, not the extra one
+        let next = Cursor::decode(&page.next_cursor.unwrap()).unwrap();
+        assert_eq!(next.id, cursor(3).id);
+    }
+
+    #[test]
+    fn page_of_exactly_limit_rows_is_the_last() {
+        let page = Page::from_rows(vec![1, 2, 3], 3, |row| cursor(*row));
+        assert_eq!(page.items, vec![1, 2, 3]);
+        assert!(page.next_cursor.is_none());
+
+        let page = Page::from_rows(Vec::<i64>::new(), 3, |row| cursor(*row));
+        assert!(page.items.is_empty());
+        assert!(page.next_cursor.is_none());
+    }
+}
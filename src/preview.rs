@@ -0,0 +1,135 @@
+use crate::chart;
+use crate::db::Database;
+use crate::email::{EmailComposer, CHART_CID};
+use crate::error::AppError;
+use crate::models::DigestEntry;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewKind {
+    Alert,
+    Welcome,
+    Digest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewFormat {
+    Html,
+    Text,
+    Chat,
+}
+
+/// Notification content exactly as it would be sent to a user
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NotificationPreview {
+    pub kind: PreviewKind,
+    pub user_id: Uuid,
+    pub recipient: String,
+    pub locale: String,
+    /// Weather reading the alert was rendered from
+    pub weather_id: Option<Uuid>,
+    pub subject: String,
+    /// The chart, if any, is inlined as a data URI so the HTML displays on its own
+    pub html: String,
+    pub text: String,
+    pub chat: Option<String>,
+}
+
+impl NotificationPreview {
+    /// Returns the content in one format, with its MIME type
+    pub fn format(&self, format: PreviewFormat) -> (&'static str, String) {
+        match format {
+            PreviewFormat::Html => ("text/html; charset=utf-8", self.html.clone()),
+            PreviewFormat::Text => ("text/plain; charset=utf-8", self.text.clone()),
+            PreviewFormat::Chat => (
+                "text/markdown; charset=utf-8",
+                self.chat.clone().unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// Renders a notification for `user_id` with the same composer the email client uses,
+/// without queueing or sending anything. Alerts are rendered from the weather reading
+/// `weather_id`, or the latest reading for the user's city, using the description stored
+/// with it, so a preview never calls the weather API; digests from the alerts the next
+/// digest will hold.
+pub async fn render(
+    db: &Database,
+    composer: &EmailComposer,
+    user_id: Uuid,
+    kind: PreviewKind,
+    weather_id: Option<Uuid>,
+) -> Result<NotificationPreview, AppError> {
+    let user = db
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let mut rendered_weather = None;
+    let email = match kind {
+        PreviewKind::Alert => {
+            let weather = match weather_id {
+                Some(id) => db.get_weather_by_id(id).await?,
+                None => db.get_latest_weather(&user.city).await?,
+            }
+            .ok_or_else(|| AppError::NotFound("No weather data found".to_string()))?;
+            let prefs = db
+                .get_user_preferences(user.id)
+                .await?
+                .ok_or_else(|| AppError::NotFound("User has no preferences".to_string()))?;
+
+            let condition = crate::check_alert_conditions(&weather, &prefs).ok_or_else(|| {
+                AppError::Validation(
+                    "This weather reading does not trigger an alert for this user".to_string(),
+                )
+            })?;
+            let message = condition.message(&user.locale, &weather.description);
+            let chart = chart::alert_chart(db, &weather.city, Some(user.id)).await;
+            rendered_weather = Some(weather.id);
+
+            composer.weather_alert(Some(user.id), &weather.city, &message, &user.locale, chart)?
+        }
+        PreviewKind::Welcome => composer.welcome(Some(user.id), &user.city, &user.locale)?,
+        PreviewKind::Digest => {
+            let alerts: Vec<DigestEntry> = db
+                .get_deferred_alerts(user.id)
+                .await?
+                .into_iter()
+                .map(DigestEntry::from)
+                .collect();
+            if alerts.is_empty() {
+                return Err(AppError::Validation(
+                    "This user has no deferred alerts, so no digest would be sent".to_string(),
+                ));
+            }
+            composer.digest(Some(user.id), &user.city, &alerts, &user.locale)?
+        }
+    };
+
+    let html = match &email.chart {
+        Some(chart) => email.body.html.replace(
+            &format!("cid:{}", CHART_CID),
+            &format!("data:image/png;base64,{}", STANDARD.encode(&chart.png)),
+        ),
+        None => email.body.html,
+    };
+
+    Ok(NotificationPreview {
+        kind,
+        user_id: user.id,
+        recipient: user.email,
+        locale: user.locale,
+        weather_id: rendered_weather,
+        subject: email.subject,
+        html,
+        text: email.body.text,
+        chat: email.chat,
+    })
+}
@@ -0,0 +1,370 @@
+use crate::auth::{bearer_token, hash_token};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::tokens::constant_time_eq;
+use crate::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpRequest, ResponseError};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Above this many tracked clients, buckets that have refilled completely are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Routes that share a limit. A request counts against every group it is routed through,
+/// e.g. a sign-up against both `Api` and `Signup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    /// Everything under `/api`
+    Api,
+    /// Sign-up and login links, which send email
+    Signup,
+    /// Manual weather fetches, which use the weather API quota
+    Fetch,
+}
+
+impl RouteGroup {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Api => "api",
+            RouteGroup::Signup => "signup",
+            RouteGroup::Fetch => "fetch",
+        }
+    }
+}
+
+/// `requests` per `period`, refilled continuously
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl Quota {
+    /// `None` for a limit of 0, which disables the group
+    pub fn new(requests: u32, period: Duration) -> Option<Self> {
+        (requests > 0).then_some(Quota { requests, period })
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.requests as f64 / self.period.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// In-memory token buckets per route group and client. Limits are per server process.
+#[derive(Clone)]
+pub struct RateLimiter {
+    quotas: Arc<HashMap<RouteGroup, Quota>>,
+    trust_proxy: bool,
+    buckets: Arc<Mutex<HashMap<(RouteGroup, String), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> Self {
+        let quotas = [
+            (RouteGroup::Api, Quota::new(config.rate_limit_api_per_minute, Duration::from_secs(60))),
+            (RouteGroup::Signup, Quota::new(config.rate_limit_signup_per_hour, Duration::from_secs(3600))),
+            (RouteGroup::Fetch, Quota::new(config.rate_limit_fetch_per_hour, Duration::from_secs(3600))),
+        ]
+        .into_iter()
+        .filter_map(|(group, quota)| quota.map(|quota| (group, quota)))
+        .collect();
+
+        RateLimiter {
+            quotas: Arc::new(quotas),
+            trust_proxy: config.rate_limit_trust_proxy,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Takes one request from the client's bucket, or returns how long until one is free
+    fn acquire(&self, group: RouteGroup, client: String) -> Result<(), Duration> {
+        self.acquire_at(group, client, Instant::now())
+    }
+
+    fn acquire_at(&self, group: RouteGroup, client: String, now: Instant) -> Result<(), Duration> {
+        let Some(quota) = self.quotas.get(&group) else {
+            return Ok(());
+        };
+        let capacity = quota.requests as f64;
+        let rate = quota.refill_per_sec();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() > PRUNE_THRESHOLD {
+            // A full bucket is the same as no bucket
+            buckets.retain(|(group, _), bucket| match self.quotas.get(group) {
+                Some(quota) => {
+                    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                    bucket.tokens + elapsed * quota.refill_per_sec() < quota.requests as f64
+                }
+                None => false,
+            });
+        }
+
+        let bucket = buckets.entry((group, client)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Who a request is counted against: the verified `account` behind its credentials, or
+    /// its IP address. Sign-ups always count against the IP address, since anyone can make
+    /// up an email address to send links to.
+    fn client_key(&self, group: RouteGroup, req: &ServiceRequest, account: Option<String>) -> String {
+        if let (Some(account), false) = (account, group == RouteGroup::Signup) {
+            return account;
+        }
+
+        let info = req.connection_info();
+        // Behind a reverse proxy the peer is the proxy; only then are forwarded headers
+        // trustworthy
+        let ip = if self.trust_proxy {
+            info.realip_remote_addr()
+        } else {
+            info.peer_addr()
+        };
+        format!("ip:{}", ip.unwrap_or("unknown"))
+    }
+}
+
+/// The account behind a request's admin key or bearer token, if the credentials are valid.
+/// Made-up credentials count against the IP address, so they can't be used to get fresh
+/// buckets.
+async fn verified_account(state: &AppState, req: &HttpRequest) -> Option<String> {
+    if let Some(key) = req.headers().get("X-Admin-Key") {
+        let valid = state
+            .config
+            .admin_api_keys
+            .iter()
+            .any(|configured| constant_time_eq(configured.as_bytes(), key.as_bytes()));
+        // Hashed, so admin keys aren't kept in memory
+        return valid.then(|| format!("admin_key:{}", hash_token(&String::from_utf8_lossy(key.as_bytes()))));
+    }
+
+    let token = bearer_token(req)?;
+    match state.db.get_api_token(&hash_token(&token)).await {
+        Ok(api_token) => api_token.map(|api_token| format!("user:{}", api_token.user_id)),
+        Err(e) => {
+            log::warn!("⚠️ Could not check token for rate limiting: {}", e);
+            None
+        }
+    }
+}
+
+/// Whole seconds for a `Retry-After` header; never 0, which would invite an immediate retry
+fn retry_after_secs(wait: Duration) -> u64 {
+    (wait.as_secs_f64().ceil() as u64).max(1)
+}
+
+/// Middleware limiting a route group with the app's `RateLimiter`. Rejected requests get
+/// `429 Too Many Requests` with a `Retry-After` header.
+pub struct RateLimit {
+    group: RouteGroup,
+}
+
+impl RateLimit {
+    pub fn new(group: RouteGroup) -> Self {
+        RateLimit { group }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            group: self.group,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    group: RouteGroup,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let group = self.group;
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+
+        Box::pin(async move {
+            if let Some(state) = state {
+                let limiter = &state.rate_limiter;
+                let account = match group {
+                    RouteGroup::Signup => None,
+                    _ => verified_account(&state, req.request()).await,
+                };
+                let client = limiter.client_key(group, &req, account);
+
+                if let Err(wait) = limiter.acquire(group, client.clone()) {
+                    log::warn!(
+                        "🚦 Rate limit ({}) hit by {} on {}",
+                        group.as_str(),
+                        // Enough of a key hash to tell clients apart
+                        &client[..client.len().min(24)],
+                        req.path()
+                    );
+                    let response = AppError::RateLimited(retry_after_secs(wait)).error_response();
+                    return Ok(req.into_response(response).map_into_right_body());
+                }
+            }
+
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn limiter(requests: u32, period: Duration, trust_proxy: bool) -> RateLimiter {
+        let quotas = [RouteGroup::Api, RouteGroup::Signup]
+            .into_iter()
+            .filter_map(|group| Quota::new(requests, period).map(|quota| (group, quota)))
+            .collect();
+        RateLimiter {
+            quotas: Arc::new(quotas),
+            trust_proxy,
+            buckets: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn acquire_allows_a_burst_up_to_the_quota() {
+        let limiter = limiter(3, Duration::from_secs(60), false);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), now).is_ok());
+        }
+        let wait = limiter
+            .acquire_at(RouteGroup::Api, "ip:1".to_string(), now)
+            .unwrap_err();
+        // One request every 20 seconds
+        assert_eq!(wait.as_secs(), 20);
+    }
+
+    #[test]
+    fn acquire_refills_over_time() {
+        let limiter = limiter(2, Duration::from_secs(10), false);
+        let now = Instant::now();
+
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), now).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), now).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), now).is_err());
+
+        let later = now + Duration::from_secs(5);
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), later).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), later).is_err());
+
+        // The bucket never holds more than the quota, however long it sat unused
+        let much_later = now + Duration::from_secs(3600);
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), much_later).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), much_later).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), much_later).is_err());
+    }
+
+    #[test]
+    fn acquire_keeps_clients_and_groups_apart() {
+        let limiter = limiter(1, Duration::from_secs(60), false);
+        let now = Instant::now();
+
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), now).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:1".to_string(), now).is_err());
+        assert!(limiter.acquire_at(RouteGroup::Api, "ip:2".to_string(), now).is_ok());
+        assert!(limiter.acquire_at(RouteGroup::Signup, "ip:1".to_string(), now).is_ok());
+    }
+
+    #[test]
+    fn acquire_ignores_groups_without_a_quota() {
+        let limiter = limiter(1, Duration::from_secs(60), false);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(limiter.acquire_at(RouteGroup::Fetch, "ip:1".to_string(), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn retry_after_rounds_up_to_whole_seconds() {
+        assert_eq!(retry_after_secs(Duration::from_millis(1)), 1);
+        assert_eq!(retry_after_secs(Duration::ZERO), 1);
+        assert_eq!(retry_after_secs(Duration::from_millis(2500)), 3);
+        assert_eq!(retry_after_secs(Duration::from_secs(20)), 20);
+    }
+
+    #[test]
+    fn client_key_uses_verified_accounts_except_for_sign_ups() {
+        let limiter = limiter(1, Duration::from_secs(60), false);
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("Authorization", "Bearer made-up"))
+            .to_srv_request();
+        let account = Some("user:42".to_string());
+
+        assert_eq!(limiter.client_key(RouteGroup::Api, &req, account.clone()), "user:42");
+        assert_eq!(limiter.client_key(RouteGroup::Signup, &req, account), "ip:10.0.0.1");
+        // Credentials that didn't verify count against the IP address
+        assert_eq!(limiter.client_key(RouteGroup::Api, &req, None), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn client_key_trusts_forwarded_headers_only_behind_a_proxy() {
+        let req = || {
+            TestRequest::default()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header(("X-Forwarded-For", "203.0.113.7"))
+                .to_srv_request()
+        };
+
+        let direct = limiter(1, Duration::from_secs(60), false);
+        assert_eq!(direct.client_key(RouteGroup::Api, &req(), None), "ip:10.0.0.1");
+
+        let proxied = limiter(1, Duration::from_secs(60), true);
+        assert_eq!(proxied.client_key(RouteGroup::Api, &req(), None), "ip:203.0.113.7");
+    }
+}
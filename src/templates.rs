@@ -0,0 +1,304 @@
+use crate::error::AppError;
+use crate::i18n;
+use log::info;
+use minijinja::value::Kwargs;
+use minijinja::{Environment, State};
+use serde::Serialize;
+use std::path::Path;
+
+// Built-in templates, used for any template the operator has not overridden
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("alert.html", include_str!("../templates/alert.html")),
+    ("welcome.html", include_str!("../templates/welcome.html")),
+    ("test.html", include_str!("../templates/test.html")),
+    ("verify.html", include_str!("../templates/verify.html")),
+    ("digest.html", include_str!("../templates/digest.html")),
+    ("login.html", include_str!("../templates/login.html")),
+    ("alert.md", include_str!("../templates/alert.md")),
+    ("welcome.md", include_str!("../templates/welcome.md")),
+    ("verify.md", include_str!("../templates/verify.md")),
+    ("digest.md", include_str!("../templates/digest.md")),
+];
+
+/// Rendered email content: the HTML part and its plain-text alternative
+#[derive(Debug, Clone)]
+pub struct RenderedEmail {
+    pub html: String,
+    pub text: String,
+}
+
+/// Email templates. Each email `name` is rendered from `name.html`; an optional
+/// `name.txt` overrides the plain-text part, which is otherwise generated from the HTML.
+/// `name.md` is the short Markdown version used for chat channels.
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Loads the built-in templates, then any `.html` / `.txt` / `.md` files found in `dir`,
+    /// which replace the built-in template of the same name.
+    pub fn load(dir: &str) -> Result<Self, AppError> {
+        let mut env = Environment::new();
+        // HTML templates are auto-escaped, plain-text ones are not
+        env.set_auto_escape_callback(|name| {
+            if name.ends_with(".html") {
+                minijinja::AutoEscape::Html
+            } else {
+                minijinja::AutoEscape::None
+            }
+        });
+
+        // `{{ t("key", name=value) }}` looks `key` up in the catalog for the `locale` in
+        // the render context
+        env.add_function("t", translate);
+
+        for (name, source) in DEFAULT_TEMPLATES {
+            env.add_template(name, source).map_err(template_error)?;
+        }
+
+        let dir = Path::new(dir);
+        if dir.is_dir() {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                let is_template = matches!(
+                    path.extension().and_then(|e| e.to_str()),
+                    Some("html") | Some("txt") | Some("md")
+                );
+                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                if !is_template {
+                    continue;
+                }
+
+                let source = std::fs::read_to_string(&path)?;
+                env.add_template_owned(name.to_string(), source)
+                    .map_err(template_error)?;
+                info!("📝 Loaded email template override: {}", path.display());
+            }
+        }
+
+        Ok(Self { env })
+    }
+
+    pub fn render<S: Serialize>(&self, name: &str, ctx: S) -> Result<RenderedEmail, AppError> {
+        let ctx = minijinja::Value::from_serialize(&ctx);
+
+        let html = self
+            .env
+            .get_template(&format!("{}.html", name))
+            .and_then(|t| t.render(&ctx))
+            .map_err(template_error)?;
+
+        let text = match self.env.get_template(&format!("{}.txt", name)) {
+            Ok(template) => template.render(&ctx).map_err(template_error)?,
+            Err(_) => html_to_text(&html),
+        };
+
+        Ok(RenderedEmail { html, text })
+    }
+
+    /// Renders the chat version of `name`, or `None` if there is no `name.md` template
+    pub fn render_chat<S: Serialize>(&self, name: &str, ctx: S) -> Result<Option<String>, AppError> {
+        match self.env.get_template(&format!("{}.md", name)) {
+            Ok(template) => Ok(Some(template.render(ctx).map_err(template_error)?.trim().to_string())),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn translate(state: &State, key: &str, kwargs: Kwargs) -> Result<String, minijinja::Error> {
+    let locale = state
+        .lookup("locale")
+        .and_then(|locale| locale.as_str().map(str::to_string))
+        .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_string());
+
+    let mut args = Vec::new();
+    for name in kwargs.args() {
+        let value: minijinja::Value = kwargs.get(name)?;
+        args.push((name, value.to_string()));
+    }
+    let args: Vec<(&str, &str)> = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+
+    Ok(i18n::t(&locale, key, &args))
+}
+
+fn template_error(err: minijinja::Error) -> AppError {
+    AppError::Email(format!("Template error: {:#}", err))
+}
+
+/// Produces a readable plain-text version of an HTML email: drops `<head>`, `<style>`
+/// and `<script>`, turns block elements into line breaks and list items into bullets,
+/// writes link targets after the link text, strips the remaining tags and decodes entities.
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() / 2);
+    let mut skip_until: Option<String> = None;
+    let mut link_href: Option<String> = None;
+    let mut chars = html.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            if skip_until.is_none() {
+                // Source indentation and line breaks carry no meaning in HTML
+                if c.is_whitespace() {
+                    if !out.ends_with([' ', '\n']) {
+                        out.push(' ');
+                    }
+                } else {
+                    out.push(c);
+                }
+            }
+            continue;
+        }
+
+        let mut tag = String::new();
+        for t in chars.by_ref() {
+            if t == '>' {
+                break;
+            }
+            tag.push(t);
+        }
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if let Some(until) = &skip_until {
+            if closing && *until == name {
+                skip_until = None;
+            }
+            continue;
+        }
+
+        match name.as_str() {
+            "head" | "style" | "script" if !closing => skip_until = Some(name),
+            "br" => newline(&mut out, 1),
+            "a" if !closing => link_href = attribute(&tag, "href"),
+            "a" => {
+                if let Some(href) = link_href.take() {
+                    out.push_str(&format!(" ({})", href));
+                }
+            }
+            "li" if !closing => {
+                newline(&mut out, 1);
+                out.push_str("- ");
+            }
+            "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "table"
+            | "tr" => newline(&mut out, 2),
+            _ => {}
+        }
+    }
+
+    let decoded = decode_entities(&out);
+    let mut text = String::with_capacity(decoded.len());
+    let mut blank_run = 0;
+    for line in decoded.lines().map(str::trim) {
+        if line.is_empty() {
+            blank_run += 1;
+            if blank_run > 1 || text.is_empty() {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.trim_end().to_string() + "\n"
+}
+
+/// Reads a double-quoted attribute from the inside of a tag, e.g. `a href="..."`
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = start + tag[start..].find('"')?;
+    Some(decode_entities(&tag[start..end]))
+}
+
+fn newline(out: &mut String, count: usize) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    let existing = out.len() - out.trim_end_matches('\n').len();
+    for _ in existing..count {
+        out.push('\n');
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&#x2f;", "/")
+        .replace("&#x2F;", "/")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_head_style_and_script() {
+        let html = r#"<html><head><title>Alert</title><style>p { color: red; }</style></head>
+            <body><script>alert("x")</script><p>Rain in London</p></body></html>"#;
+
+        assert_eq!(html_to_text(html), "Rain in London\n");
+    }
+
+    #[test]
+    fn blocks_become_paragraphs_and_br_line_breaks() {
+        let html = "<h1>Weather</h1><p>Line one<br>line two</p><div>Next</div>";
+
+        assert_eq!(html_to_text(html), "Weather\n\nLine one\nline two\n\nNext\n");
+    }
+
+    #[test]
+    fn list_items_become_bullets() {
+        let html = "<ul><li>Rain</li><li>Snow</li></ul>";
+
+        assert_eq!(html_to_text(html), "- Rain\n- Snow\n");
+    }
+
+    #[test]
+    fn links_keep_their_target() {
+        let html = r#"<p><a href="https://example.com/unsubscribe?a=1&amp;b=2">Unsubscribe</a></p>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "Unsubscribe (https://example.com/unsubscribe?a=1&b=2)\n"
+        );
+    }
+
+    #[test]
+    fn collapses_whitespace_and_blank_lines() {
+        let html = "<p>  Too   much\n\n   space  </p>\n\n<p></p><p></p>\n<p>End</p>";
+
+        assert_eq!(html_to_text(html), "Too much space\n\nEnd\n");
+    }
+
+    #[test]
+    fn decodes_entities_once() {
+        let html = "<p>5 &lt; 10 &amp;&amp; &quot;hot&quot; &#39;today&#39;&nbsp;&amp;lt;</p>";
+
+        assert_eq!(html_to_text(html), "5 < 10 && \"hot\" 'today' &lt;\n");
+    }
+
+    #[test]
+    fn tag_names_are_case_insensitive() {
+        let html = "<P>One</P><STYLE>x</STYLE><Br/>Two";
+
+        assert_eq!(html_to_text(html), "One\n\nTwo\n");
+    }
+
+    #[test]
+    fn handles_empty_and_unclosed_input() {
+        assert_eq!(html_to_text(""), "\n");
+        assert_eq!(html_to_text("plain text"), "plain text\n");
+        assert_eq!(html_to_text("text <p unclosed"), "text\n");
+    }
+}
@@ -0,0 +1,240 @@
+use crate::error::AppError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Compares two secrets without leaking where they differ through timing
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// What a signed token may be used for. A token is only accepted by endpoints expecting
+/// its purpose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    /// One-click unsubscribe from alert emails
+    Unsubscribe,
+    /// Managing the subscription (preferences, pause/resume, delete) without logging in
+    Preferences,
+    /// Confirming the email address of a newly registered user
+    Verify,
+    /// Magic-link login, exchanged for an API token
+    Login,
+    /// Opening the live event stream, since browsers' `EventSource` can't send headers
+    Stream,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: Uuid,
+    purpose: TokenPurpose,
+    exp: i64,
+    /// Address the token was sent to, for tokens that confirm that address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+}
+
+/// Issues and checks stateless, expiring tokens of the form `<payload>.<signature>`,
+/// where the payload is base64url JSON and the signature is HMAC-SHA256 over it.
+#[derive(Clone)]
+pub struct TokenSigner {
+    secret: Vec<u8>,
+}
+
+impl TokenSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn sign(&self, user_id: Uuid, purpose: TokenPurpose, ttl: Duration) -> String {
+        self.sign_claims(Claims {
+            sub: user_id,
+            purpose,
+            exp: (Utc::now() + ttl).timestamp(),
+            email: None,
+        })
+    }
+
+    /// Like [`sign`](Self::sign), but the token also names the address it is sent to,
+    /// so it stops confirming anything once the user's address changes.
+    pub fn sign_for_address(
+        &self,
+        user_id: Uuid,
+        email: &str,
+        purpose: TokenPurpose,
+        ttl: Duration,
+    ) -> String {
+        self.sign_claims(Claims {
+            sub: user_id,
+            purpose,
+            exp: (Utc::now() + ttl).timestamp(),
+            email: Some(email.to_string()),
+        })
+    }
+
+    fn sign_claims(&self, claims: Claims) -> String {
+        // Serializing a struct of plain fields cannot fail
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap_or_default());
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// Returns the user the token was issued for, if the token is authentic, unexpired
+    /// and was issued for `purpose`.
+    pub fn verify(&self, token: &str, purpose: TokenPurpose) -> Result<Uuid, AppError> {
+        self.verify_claims(token, purpose).map(|claims| claims.sub)
+    }
+
+    /// Returns the user and address a token from [`sign_for_address`](Self::sign_for_address)
+    /// was issued for. Tokens without an address are rejected.
+    pub fn verify_for_address(
+        &self,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<(Uuid, String), AppError> {
+        let claims = self.verify_claims(token, purpose)?;
+        let email = claims
+            .email
+            .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+        Ok((claims.sub, email))
+    }
+
+    fn verify_claims(&self, token: &str, purpose: TokenPurpose) -> Result<Claims, AppError> {
+        let invalid = || AppError::Unauthorized("Invalid or expired token".to_string());
+
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        let claims: Claims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(invalid)?;
+
+        if claims.purpose != purpose || claims.exp < Utc::now().timestamp() {
+            return Err(invalid());
+        }
+
+        Ok(claims)
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> TokenSigner {
+        TokenSigner::new("test-secret-with-enough-length")
+    }
+
+    #[test]
+    fn verify_accepts_a_fresh_token() {
+        let user_id = Uuid::new_v4();
+        let token = signer().sign(user_id, TokenPurpose::Unsubscribe, Duration::minutes(5));
+
+        assert_eq!(signer().verify(&token, TokenPurpose::Unsubscribe).unwrap(), user_id);
+    }
+
+    #[test]
+    fn verify_rejects_the_wrong_purpose() {
+        let token = signer().sign(Uuid::new_v4(), TokenPurpose::Preferences, Duration::minutes(5));
+
+        assert!(matches!(
+            signer().verify(&token, TokenPurpose::Unsubscribe),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn verify_rejects_expired_tokens() {
+        let token = signer().sign(Uuid::new_v4(), TokenPurpose::Unsubscribe, Duration::seconds(-1));
+
+        assert!(signer().verify(&token, TokenPurpose::Unsubscribe).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_another_secret() {
+        let token = signer().sign(Uuid::new_v4(), TokenPurpose::Preferences, Duration::minutes(5));
+
+        assert!(TokenSigner::new("another-secret")
+            .verify(&token, TokenPurpose::Preferences)
+            .is_err());
+    }
+
+    #[test]
+    fn verify_rejects_a_tampered_payload() {
+        let token = signer().sign(Uuid::new_v4(), TokenPurpose::Preferences, Duration::minutes(5));
+        let (_, signature) = token.split_once('.').unwrap();
+
+        // Same signature over claims for another user
+        let forged = Claims {
+            sub: Uuid::new_v4(),
+            purpose: TokenPurpose::Preferences,
+            exp: (Utc::now() + Duration::minutes(5)).timestamp(),
+            email: None,
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        let tampered = format!("{}.{}", payload, signature);
+
+        assert!(signer().verify(&tampered, TokenPurpose::Preferences).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_malformed_tokens() {
+        let token = signer().sign(Uuid::new_v4(), TokenPurpose::Unsubscribe, Duration::minutes(5));
+        let (payload, _) = token.split_once('.').unwrap();
+
+        for malformed in ["", "no-dot", payload, &format!("{}.", payload), &format!("{}.!!", payload)] {
+            assert!(signer().verify(malformed, TokenPurpose::Unsubscribe).is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn verify_for_address_returns_the_signed_address() {
+        let user_id = Uuid::new_v4();
+        let token = signer().sign_for_address(
+            user_id,
+            "new@example.com",
+            TokenPurpose::Verify,
+            Duration::minutes(5),
+        );
+
+        assert_eq!(
+            signer().verify_for_address(&token, TokenPurpose::Verify).unwrap(),
+            (user_id, "new@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn verify_for_address_rejects_tokens_without_an_address() {
+        let token = signer().sign(Uuid::new_v4(), TokenPurpose::Verify, Duration::minutes(5));
+
+        assert!(signer().verify_for_address(&token, TokenPurpose::Verify).is_err());
+    }
+
+    #[test]
+    fn constant_time_eq_compares_contents_and_length() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
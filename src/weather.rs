@@ -18,11 +18,21 @@ impl WeatherClient {
         }
     }
 
-    pub async fn get_weather(&self, city: &str, country: &str) -> Result<WeatherData, AppError> {
-        let url = format!(
+    /// Fetches current weather. `lang` localizes the `description` text; `conditions`
+    /// is always the English group name (Rain, Snow, ...).
+    pub async fn get_weather(
+        &self,
+        city: &str,
+        country: &str,
+        lang: Option<&str>,
+    ) -> Result<WeatherData, AppError> {
+        let mut url = format!(
             "https://api.openweathermap.org/data/2.5/weather?q={},{}&appid={}&units=metric",
             city, country, self.api_key
         );
+        if let Some(lang) = lang {
+            url.push_str(&format!("&lang={}", lang));
+        }
 
         info!("🌐 Fetching weather from API: {}, {}", city, country);
 